@@ -2,7 +2,7 @@ use lazy_static::lazy_static;
 use regex::Regex;
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
-use serde_json;
+use serde_json::{Map, Value};
 use std::{
     collections::{HashMap, HashSet},
     error::Error,
@@ -13,6 +13,9 @@ use std::{
 };
 use url::Url;
 
+/// Manifest templates filled from project properties
+pub mod template;
+
 lazy_static! {
     pub static ref ID_REGEX: Regex =
         Regex::new(r#"^([A-Z][0-9a-z]*)+(\.([A-Z][0-9a-z]*)+)*$"#).unwrap();
@@ -103,6 +106,11 @@ pub struct Manifest {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub readme: Option<PathBuf>,
+
+    /// Extra data for the mod loader or other tools, like BSIPA's `plugin-hint`
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    #[serde(default)]
+    pub misc: Map<String, Value>,
 }
 
 /// Manifest validity error
@@ -194,11 +202,149 @@ pub struct OldManifest {
     pub load_before: Option<HashSet<String>>,
     pub features: Option<HashSet<String>>,
     pub icon: Option<PathBuf>,
+    /// Links of any kind, the new format only knows some of them
     #[serde(default)]
-    pub links: Links,
+    pub links: Map<String, Value>,
+    /// Fields the old format doesn't define, some tools and manifests used them anyway
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 impl OldManifest {
+    /// License set in the manifest, even though the old format doesn't define one
+    pub fn license(&self) -> Option<&str> {
+        self.extra.get("license").and_then(Value::as_str)
+    }
+
+    /// Migrates the manifest to the new format, along with what happened to the fields the new format doesn't define
+    ///
+    /// Fields without an equivalent are kept in `misc`, and the license is only used if the manifest doesn't set one.
+    /// Entries of an existing `misc` keep their keys, and fields conflicting with them are dropped.
+    pub fn migrate(mut self, license: String) -> (Manifest, Vec<MigrationNote>) {
+        let mut notes = Vec::new();
+        let mut manifest = Manifest {
+            schema: schema(),
+            id: self.id,
+            name: self.name,
+            version: self.version,
+            game_version: self.game_version,
+            description: self.description,
+            author: self.author,
+            license,
+            depends_on: self.depends_on,
+            conflicts_with: self.conflicts_with,
+            load_after: self.load_after,
+            load_before: self.load_before,
+            features: self.features,
+            icon: self.icon,
+            links: Links::default(),
+            publish: Publish::default(),
+            readme: None,
+            misc: Map::new(),
+        };
+
+        match self.extra.remove("misc") {
+            Some(Value::Object(misc)) => {
+                for (k, v) in misc {
+                    let key = format!("misc.{}", k);
+                    manifest.misc.insert(k, v);
+                    notes.push(MigrationNote::Mapped {
+                        from: key.clone(),
+                        to: key,
+                    });
+                }
+            }
+            Some(_) => notes.push(MigrationNote::Dropped {
+                key: "misc".to_owned(),
+                reason: "unexpected type".to_owned(),
+            }),
+            None => (),
+        }
+        let conflict = || "conflicts with an entry of the old `misc`".to_owned();
+
+        for (kind, value) in self.links {
+            let key = format!("links.{}", kind);
+            let url = match value.as_str().map(Url::parse) {
+                Some(Ok(url)) => url,
+                _ => {
+                    notes.push(MigrationNote::Dropped {
+                        key,
+                        reason: "not a valid URL".to_owned(),
+                    });
+                    continue;
+                }
+            };
+            let link = match kind.as_str() {
+                "project-home" => &mut manifest.links.project_home,
+                "project-source" => &mut manifest.links.project_source,
+                "donate" => &mut manifest.links.donate,
+                _ => {
+                    let links = manifest
+                        .misc
+                        .entry("links")
+                        .or_insert_with(|| Value::Object(Map::new()));
+                    match links {
+                        Value::Object(links) if !links.contains_key(&kind) => {
+                            links.insert(kind, Value::String(url.into()));
+                            notes.push(MigrationNote::Kept {
+                                to: format!("misc.{}", key),
+                                from: key,
+                            });
+                        }
+                        _ => notes.push(MigrationNote::Dropped {
+                            key,
+                            reason: conflict(),
+                        }),
+                    }
+                    continue;
+                }
+            };
+            *link = Some(url);
+        }
+
+        for (key, value) in self.extra {
+            match (key.as_str(), value) {
+                // Replaced by the new schema
+                ("$schema", _) => (),
+                ("license", Value::String(license)) => {
+                    manifest.license = license;
+                    notes.push(MigrationNote::Mapped {
+                        from: key,
+                        to: "license".to_owned(),
+                    });
+                }
+                ("readme", Value::String(readme)) => {
+                    manifest.readme = Some(PathBuf::from(readme));
+                    notes.push(MigrationNote::Mapped {
+                        from: key,
+                        to: "readme".to_owned(),
+                    });
+                }
+                ("license", _) | ("readme", _) => {
+                    notes.push(MigrationNote::Dropped {
+                        key,
+                        reason: "unexpected type".to_owned(),
+                    });
+                }
+                (_, _) if manifest.misc.contains_key(&key) => {
+                    notes.push(MigrationNote::Dropped {
+                        key,
+                        reason: conflict(),
+                    });
+                }
+                (_, value) => {
+                    manifest.misc.insert(key.clone(), value);
+                    notes.push(MigrationNote::Kept {
+                        to: format!("misc.{}", key),
+                        from: key,
+                    });
+                }
+            }
+        }
+
+        (manifest, notes)
+    }
+
     /// Reads the manifest from a JSON reader
     pub fn from_reader<R: Read>(reader: R) -> serde_json::Result<Self> {
         serde_json::from_reader(reader)
@@ -214,35 +360,164 @@ impl FromStr for OldManifest {
     }
 }
 
+/// Repository of the old BSIPA manifest schema
+const OLD_SCHEMA_REPOSITORY: &str = "ModSaber-MetadataFileSchema";
+/// Repository of the new manifest schema
+const SCHEMA_REPOSITORY: &str = "BSIPA-MetadataFileSchema";
+
+/// Manifest format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Old BSIPA manifest
+    Old,
+    /// BeatMods2 manifest
+    New,
+}
+
+impl Format {
+    /// Detects the format of a JSON manifest from its schema, or from its fields if the schema is unknown
+    pub fn detect(value: &serde_json::Value) -> Self {
+        let schema = value.get("$schema").and_then(|s| s.as_str()).unwrap_or("");
+        if schema.contains(SCHEMA_REPOSITORY) {
+            Format::New
+        } else if schema.contains(OLD_SCHEMA_REPOSITORY) {
+            Format::Old
+        } else if value.get("license").is_some() || value.get("publish").is_some() {
+            Format::New
+        } else {
+            Format::Old
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Format::Old => write!(f, "old BSIPA"),
+            Format::New => write!(f, "BeatMods2"),
+        }
+    }
+}
+
+/// Manifest in either format
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)] // Matched on right after reading
+pub enum AnyManifest {
+    Old(OldManifest),
+    New(Manifest),
+}
+
+impl AnyManifest {
+    /// Reads a manifest in either format from a JSON reader
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, LoadError> {
+        let mut s = String::new();
+        reader
+            .read_to_string(&mut s)
+            .map_err(|e| LoadError::new(None, serde_json::Error::io(e)))?;
+        s.parse()
+    }
+
+    /// Format of the manifest
+    pub fn format(&self) -> Format {
+        match self {
+            AnyManifest::Old(_) => Format::Old,
+            AnyManifest::New(_) => Format::New,
+        }
+    }
+}
+
+/// Parses a manifest in either format from a JSON string
+impl FromStr for AnyManifest {
+    type Err = LoadError;
+
+    fn from_str(s: &str) -> Result<Self, LoadError> {
+        let value: serde_json::Value =
+            serde_json::from_str(s).map_err(|e| LoadError::new(None, e))?;
+        let format = Format::detect(&value);
+        // Parsed again from the text so errors point to a line and column
+        match format {
+            Format::Old => s.parse().map(AnyManifest::Old),
+            Format::New => s.parse().map(AnyManifest::New),
+        }
+        .map_err(|e| LoadError::new(Some(format), e))
+    }
+}
+
+/// Error reading a manifest in either format
+#[derive(Debug)]
+pub struct LoadError {
+    /// Detected format, if the manifest is valid JSON
+    pub format: Option<Format>,
+    source: serde_json::Error,
+}
+
+impl LoadError {
+    fn new(format: Option<Format>, source: serde_json::Error) -> Self {
+        Self { format, source }
+    }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self.format {
+            Some(format) => write!(f, "Invalid {} manifest", format),
+            None => write!(f, "Invalid JSON"),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
 /// Migrate the old manifest to a new one
 impl From<(OldManifest, String)> for Manifest {
     fn from(m: (OldManifest, String)) -> Self {
         let (m, license) = m;
-        Self {
-            schema: schema(),
-            id: m.id,
-            name: m.name,
-            version: m.version,
-            game_version: m.game_version,
-            description: m.description,
-            author: m.author,
-            license,
-            depends_on: m.depends_on,
-            conflicts_with: m.conflicts_with,
-            load_after: m.load_after,
-            load_before: m.load_before,
-            features: m.features,
-            icon: m.icon,
-            links: m.links,
-            publish: Publish::default(),
-            readme: None,
+        m.migrate(license).0
+    }
+}
+
+/// What happened to an old manifest field the new format doesn't define
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum MigrationNote {
+    /// Moved to its new equivalent
+    Mapped { from: String, to: String },
+    /// Kept as is under `misc`
+    Kept { from: String, to: String },
+    /// Dropped because it couldn't be mapped
+    Dropped { key: String, reason: String },
+    /// Filled from the project around the manifest
+    Detected { to: String, value: String },
+}
+
+impl Display for MigrationNote {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            MigrationNote::Mapped { from, to } if from == to => {
+                write!(f, "`{}` carried over", from)
+            }
+            MigrationNote::Mapped { from, to } => write!(f, "`{}` moved to `{}`", from, to),
+            MigrationNote::Kept { from, to } => {
+                write!(f, "`{}` has no equivalent, kept as `{}`", from, to)
+            }
+            MigrationNote::Dropped { key, reason } => {
+                write!(f, "`{}` dropped, {}", key, reason)
+            }
+            MigrationNote::Detected { to, value } => {
+                write!(f, "`{}` set to detected `{}`", to, value)
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Manifest;
+    use crate::{AnyManifest, Format, Manifest, MigrationNote, OldManifest};
+    use std::error::Error;
 
     #[test]
     fn reader_writer() {
@@ -415,4 +690,169 @@ mod tests {
             .expect("Can't deserialise manifest");
         assert!(invalid_deserialised.validate().is_err());
     }
+
+    #[test]
+    fn format_detection() {
+        let old = r#"
+        {
+          "$schema": "https://raw.githubusercontent.com/nike4613/ModSaber-MetadataFileSchema/master/Schema.json",
+          "id": "ExampleMod",
+          "name": "Example Mod",
+          "version": "1.2.3",
+          "gameVersion": "0.13.2",
+          "description": ["This is an example mod."],
+          "author": "DaNike"
+        }
+        "#;
+        assert_eq!(old.parse::<AnyManifest>().unwrap().format(), Format::Old);
+
+        let unversioned = old.replace("$schema", "comment");
+        assert_eq!(
+            unversioned.parse::<AnyManifest>().unwrap().format(),
+            Format::Old
+        );
+        let licensed = unversioned.replace(r#""author""#, r#""license": "MIT", "author""#);
+        assert_eq!(
+            licensed.parse::<AnyManifest>().unwrap().format(),
+            Format::New
+        );
+
+        let missing_license = old.replace("nike4613/ModSaber", "raftario/BSIPA");
+        let error = missing_license.parse::<AnyManifest>().unwrap_err();
+        assert_eq!(error.format, Some(Format::New));
+        assert_eq!(error.to_string(), "Invalid BeatMods2 manifest");
+        assert!(error
+            .source()
+            .unwrap()
+            .to_string()
+            .starts_with("missing field `license` at line 10"));
+
+        assert_eq!("[".parse::<AnyManifest>().unwrap_err().format, None);
+    }
+    #[test]
+    fn lossless_migration() {
+        let old = r#"
+        {
+          "$schema": "https://raw.githubusercontent.com/nike4613/ModSaber-MetadataFileSchema/master/Schema.json",
+          "id": "ExampleMod",
+          "name": "Example Mod",
+          "version": "1.2.3",
+          "gameVersion": "1.12.2",
+          "description": ["This is an example mod."],
+          "author": "DaNike",
+          "license": "MIT",
+          "links": {
+            "project-home": "https://example.com",
+            "donate": "not a url",
+            "discord": "https://discord.gg/example"
+          },
+          "misc": {
+            "plugin-hint": "ExampleMod.Plugin"
+          },
+          "files": ["ExampleMod.dll"]
+        }
+        "#;
+        let old: OldManifest = old.parse().unwrap();
+        assert_eq!(old.license(), Some("MIT"));
+
+        let (manifest, notes) = old.migrate("GPL-3.0-or-later".to_owned());
+        assert_eq!(manifest.license, "MIT");
+        assert_eq!(
+            manifest.links.project_home.as_ref().map(|u| u.as_str()),
+            Some("https://example.com/")
+        );
+        assert_eq!(manifest.links.donate, None);
+        assert_eq!(manifest.misc["plugin-hint"], "ExampleMod.Plugin");
+        assert_eq!(
+            manifest.misc["links"]["discord"],
+            "https://discord.gg/example"
+        );
+        assert_eq!(manifest.misc["files"][0], "ExampleMod.dll");
+        assert_eq!(
+            notes,
+            vec![
+                MigrationNote::Mapped {
+                    from: "misc.plugin-hint".to_owned(),
+                    to: "misc.plugin-hint".to_owned(),
+                },
+                MigrationNote::Kept {
+                    from: "links.discord".to_owned(),
+                    to: "misc.links.discord".to_owned(),
+                },
+                MigrationNote::Dropped {
+                    key: "links.donate".to_owned(),
+                    reason: "not a valid URL".to_owned(),
+                },
+                MigrationNote::Kept {
+                    from: "files".to_owned(),
+                    to: "misc.files".to_owned(),
+                },
+                MigrationNote::Mapped {
+                    from: "license".to_owned(),
+                    to: "license".to_owned(),
+                },
+            ]
+        );
+
+        let reparsed: Manifest = manifest.to_string().unwrap().parse().unwrap();
+        assert_eq!(reparsed, manifest);
+    }
+
+    #[test]
+    fn misc_collisions() {
+        let old = r#"
+        {
+          "id": "ExampleMod",
+          "name": "Example Mod",
+          "version": "1.2.3",
+          "gameVersion": "1.12.2",
+          "description": [],
+          "author": "DaNike",
+          "links": {
+            "discord": "https://discord.gg/new",
+            "patreon": "https://patreon.com/example"
+          },
+          "misc": {
+            "plugin-hint": "ExampleMod.Plugin",
+            "links": { "discord": "https://discord.gg/old" }
+          },
+          "plugin-hint": "ExampleMod.Other"
+        }
+        "#;
+        let old: OldManifest = old.parse().unwrap();
+
+        let (manifest, notes) = old.migrate("MIT".to_owned());
+        assert_eq!(manifest.misc["plugin-hint"], "ExampleMod.Plugin");
+        assert_eq!(manifest.misc["links"]["discord"], "https://discord.gg/old");
+        assert_eq!(
+            manifest.misc["links"]["patreon"],
+            "https://patreon.com/example"
+        );
+        let conflict = "conflicts with an entry of the old `misc`".to_owned();
+        assert_eq!(
+            notes,
+            vec![
+                MigrationNote::Mapped {
+                    from: "misc.links".to_owned(),
+                    to: "misc.links".to_owned(),
+                },
+                MigrationNote::Mapped {
+                    from: "misc.plugin-hint".to_owned(),
+                    to: "misc.plugin-hint".to_owned(),
+                },
+                MigrationNote::Dropped {
+                    key: "links.discord".to_owned(),
+                    reason: conflict.clone(),
+                },
+                MigrationNote::Kept {
+                    from: "links.patreon".to_owned(),
+                    to: "misc.links.patreon".to_owned(),
+                },
+                MigrationNote::Dropped {
+                    key: "plugin-hint".to_owned(),
+                    reason: conflict,
+                },
+            ]
+        );
+    }
 }
@@ -0,0 +1,189 @@
+use crate::{Manifest, ValidityError};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+/// Name of the manifest template files rendered in place of `manifest.json`
+pub const TEMPLATE_NAME: &str = "manifest.template.json";
+
+lazy_static! {
+    static ref PROPERTY_GROUP_REGEX: Regex =
+        Regex::new(r#"(?s)<PropertyGroup(?:\s[^>]*)?>(.*?)</PropertyGroup>"#).unwrap();
+    static ref PROPERTY_REGEX: Regex =
+        Regex::new(r#"<([\w.]+)(?:\s[^>]*)?>([^<]*)</([\w.]+)>"#).unwrap();
+}
+
+/// Manifest template error
+#[derive(Debug)]
+pub enum TemplateError {
+    /// A placeholder isn't closed
+    Unclosed,
+    /// A placeholder has no value
+    Unknown(String),
+    /// The rendered manifest can't be parsed
+    Parse(serde_json::Error),
+    /// The rendered manifest isn't valid
+    Invalid(ValidityError),
+}
+
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            TemplateError::Unclosed => write!(f, "Unclosed placeholder, expected `}}`"),
+            TemplateError::Unknown(name) => match name.strip_prefix("env:") {
+                Some(var) => write!(f, "Environment variable `{}` isn't set", var),
+                None => write!(f, "Unknown placeholder `${{{}}}`", name),
+            },
+            TemplateError::Parse(_) => write!(f, "Invalid rendered manifest"),
+            TemplateError::Invalid(_) => write!(f, "Rendered manifest isn't valid"),
+        }
+    }
+}
+
+impl Error for TemplateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TemplateError::Parse(e) => Some(e),
+            TemplateError::Invalid(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Replaces `${name}` placeholders with the values returned by `lookup`, `$${` being a literal `${`
+pub fn render<F: FnMut(&str) -> Option<String>>(
+    text: &str,
+    mut lookup: F,
+) -> Result<String, TemplateError> {
+    let mut rendered = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let (before, after) = (&rest[..start], &rest[start + 2..]);
+        if let Some(before) = before.strip_suffix('$') {
+            rendered.push_str(before);
+            rendered.push_str("${");
+            rest = after;
+            continue;
+        }
+        rendered.push_str(before);
+
+        let end = after.find('}').ok_or(TemplateError::Unclosed)?;
+        let name = &after[..end];
+        let value = lookup(name).ok_or_else(|| TemplateError::Unknown(name.to_owned()))?;
+        rendered.push_str(&value);
+        rest = &after[end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Simple MSBuild properties of a project file, ignoring ones referencing other properties
+///
+/// The first definition of a property wins, like conditionless properties would in most projects.
+pub fn msbuild_properties(xml: &str) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+    for group in PROPERTY_GROUP_REGEX.captures_iter(xml) {
+        for c in PROPERTY_REGEX.captures_iter(&group[1]) {
+            let value = c[2].trim();
+            if c[1] == c[3] && !value.is_empty() && !value.contains("$(") {
+                properties
+                    .entry(c[1].to_owned())
+                    .or_insert_with(|| value.to_owned());
+            }
+        }
+    }
+    properties
+}
+
+/// Value of a manifest template placeholder
+///
+/// `${env:NAME}` is an environment variable and `${name}` the `name` or `Name` project property,
+/// so `${version}` and `${gameVersion}` are filled from `Version` and `GameVersion`.
+fn placeholder(name: &str, properties: &HashMap<String, String>) -> Option<String> {
+    if let Some(var) = name.strip_prefix("env:") {
+        return env::var(var).ok();
+    }
+    properties.get(name).cloned().or_else(|| {
+        let mut chars = name.chars();
+        let first = chars.next()?;
+        let pascal_case: String = first.to_uppercase().chain(chars).collect();
+        properties.get(&pascal_case).cloned()
+    })
+}
+
+/// Renders a manifest template and checks the result
+///
+/// Placeholders are expected inside JSON strings, so their values are escaped.
+pub fn render_manifest(
+    template: &str,
+    properties: &HashMap<String, String>,
+) -> Result<Manifest, TemplateError> {
+    let rendered = render(template, |name| {
+        let value = serde_json::to_string(&placeholder(name, properties)?).ok()?;
+        Some(value[1..value.len() - 1].to_owned())
+    })?;
+    let manifest: Manifest = rendered.parse().map_err(TemplateError::Parse)?;
+    manifest.validate().map_err(TemplateError::Invalid)?;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::template::{self, TemplateError};
+    use semver::Version;
+    use std::env;
+
+    #[test]
+    fn render_manifest() {
+        let properties = template::msbuild_properties(
+            r#"<Project Sdk="Microsoft.NET.Sdk">
+  <PropertyGroup>
+    <Version>1.2.3</Version>
+    <GameVersion>1.12.2</GameVersion>
+    <OutputPath>$(BeatSaberDir)</OutputPath>
+  </PropertyGroup>
+  <ItemGroup>
+    <Reference Include="IPA.Loader"><HintPath>Beat Saber_Data</HintPath></Reference>
+  </ItemGroup>
+</Project>"#,
+        );
+        assert_eq!(properties.len(), 2);
+
+        env::set_var("BM2_TEST_AUTHOR", "Da\"Nike");
+        let template = r#"{
+          "id": "ExampleMod",
+          "name": "Example Mod",
+          "version": "${version}",
+          "gameVersion": "${gameVersion}",
+          "description": ["Costs $${price}"],
+          "author": "${env:BM2_TEST_AUTHOR}",
+          "license": "MIT"
+        }"#;
+        let manifest = template::render_manifest(template, &properties).unwrap();
+        assert_eq!(manifest.version, Version::new(1, 2, 3));
+        assert_eq!(manifest.game_version, "1.12.2");
+        assert_eq!(manifest.description, vec!["Costs ${price}"]);
+        assert_eq!(manifest.author, "Da\"Nike");
+
+        let missing = template.replace("BM2_TEST_AUTHOR", "BM2_TEST_UNSET");
+        let error = template::render_manifest(&missing, &properties).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Environment variable `BM2_TEST_UNSET` isn't set"
+        );
+        let invalid = template.replace("ExampleMod", "example mod");
+        assert!(matches!(
+            template::render_manifest(&invalid, &properties),
+            Err(TemplateError::Invalid(_))
+        ));
+        assert!(matches!(
+            template::render("${id", |_| None),
+            Err(TemplateError::Unclosed)
+        ));
+    }
+}
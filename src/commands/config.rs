@@ -1,13 +1,20 @@
 #[cfg_attr(not(windows), allow(unused_imports))]
 use crate::{
-    commands::Run,
-    config::Config as AppConfig,
-    globals::{CONFIG_PATH, TERM_ERR, TERM_OUT},
+    commands::{GlobalOpts, Run},
+    terminal::{TERM_ERR, TERM_OUT},
+};
+use anyhow::{anyhow, Context, Result};
+use bm2::{
+    config::{self, Config as AppConfig, Entry},
+    error::{ErrorKind, ResultExt},
+    globals::CONFIG_PATH,
+    progress::Reporter,
 };
-use anyhow::{Context, Result};
 use cfg_if::cfg_if;
-use dialoguer::Editor;
-use indicatif::ProgressBar;
+use dialoguer::{Editor, Select};
+use serde::Serialize;
+use serde_json::Value;
+use std::{fs, path::PathBuf};
 use structopt::StructOpt;
 
 #[cfg(windows)]
@@ -19,41 +26,252 @@ pub struct Config {
     /// Print the file path instead of opening an editor
     #[structopt(short = "P", long)]
     print_path: bool,
+
+    #[structopt(subcommand)]
+    cmd: Option<ConfigCommand>,
+}
+
+/// Config subcommands, the global config file is edited without one
+#[derive(StructOpt, Debug)]
+enum ConfigCommand {
+    /// Prints the effective config, merged from the global and project files and the environment, without secrets
+    Show {
+        /// Prints every value along with where it comes from
+        #[structopt(long)]
+        origin: bool,
+    },
+    /// Prints the effective value of a key, like `autoUpdate` or `defaults.author`
+    Get { key: String },
+    /// Sets a key in the global config file
+    Set {
+        key: String,
+        value: String,
+        /// Changes the project config file instead
+        #[structopt(long)]
+        project: bool,
+    },
+    /// Removes a key from the global config file
+    Unset {
+        key: String,
+        /// Changes the project config file instead
+        #[structopt(long)]
+        project: bool,
+    },
+    /// Lists the effective value of every key, without secrets
+    List,
+}
+
+/// Config command output
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum ConfigOutput {
+    Edited(Edited),
+    Effective(Value),
+    Entry(Entry),
+    Entries(Vec<Entry>),
+    Changed(Changed),
+}
+
+/// Outcome of editing the global config file
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Edited {
+    path: PathBuf,
+    saved: bool,
+    /// Copy of invalid changes the user chose to keep
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rejected: Option<PathBuf>,
+}
+
+/// Outcome of setting or unsetting a key
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Changed {
+    path: PathBuf,
+    key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<Value>,
+    changed: bool,
+}
+
+/// Value as printed for humans, strings without quotes
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+/// Path invalid edits of the global config file are saved to
+fn rej_path() -> PathBuf {
+    let mut path = CONFIG_PATH.as_os_str().to_owned();
+    path.push(".rej");
+    PathBuf::from(path)
+}
+
+/// Config file changed by `set` and `unset`
+fn target_path(project: bool) -> Result<PathBuf> {
+    if project {
+        config::project_config_path()
+    } else {
+        Ok(CONFIG_PATH.clone())
+    }
 }
 
 impl Run for Config {
-    #[cfg_attr(not(windows), allow(unused_variables))]
-    fn run(self, verbose: bool) -> Result<()> {
+    type Output = ConfigOutput;
+
+    fn run(self, opts: &GlobalOpts, reporter: &dyn Reporter) -> Result<ConfigOutput> {
+        let cmd = match self.cmd {
+            Some(cmd) => cmd,
+            None => return Ok(ConfigOutput::Edited(self.edit(opts, reporter)?)),
+        };
+        match cmd {
+            ConfigCommand::Show { origin: false } => {
+                let mut config =
+                    serde_json::to_value(AppConfig::read().category(ErrorKind::Config)?)?;
+                config::mask_secrets("", &mut config);
+                if !opts.json() {
+                    TERM_OUT.write_line(&serde_json::to_string_pretty(&config)?)?;
+                }
+                Ok(ConfigOutput::Effective(config))
+            }
+            ConfigCommand::Show { origin: true } | ConfigCommand::List => {
+                let show_origin = matches!(cmd, ConfigCommand::Show { .. });
+                let mut entries = AppConfig::entries().category(ErrorKind::Config)?;
+                for e in &mut entries {
+                    config::mask_secrets(e.key, &mut e.value);
+                }
+                if !opts.json() {
+                    let width = entries.iter().map(|e| e.key.len()).max().unwrap_or(0);
+                    for e in &entries {
+                        let mut line = format!("{:width$} = {}", e.key, e.value, width = width);
+                        if show_origin {
+                            line.push_str(&format!("  ({})", e.origin));
+                        }
+                        TERM_OUT.write_line(&line)?;
+                    }
+                }
+                Ok(ConfigOutput::Entries(entries))
+            }
+            ConfigCommand::Get { key } => {
+                let entry = AppConfig::get(&key).category(ErrorKind::Config)?;
+                if !opts.json() && !entry.value.is_null() {
+                    TERM_OUT.write_line(&display_value(&entry.value))?;
+                }
+                Ok(ConfigOutput::Entry(entry))
+            }
+            ConfigCommand::Set {
+                key,
+                value,
+                project,
+            } => {
+                let path = target_path(project)?;
+                let value = config::set_in_file(&path, &key, &value).category(ErrorKind::Config)?;
+                reporter.detail(&format!("Set `{}` in {}", key, path.display()));
+                Ok(ConfigOutput::Changed(Changed {
+                    path,
+                    key,
+                    value: Some(value),
+                    changed: true,
+                }))
+            }
+            ConfigCommand::Unset { key, project } => {
+                let path = target_path(project)?;
+                let changed = config::unset_in_file(&path, &key).category(ErrorKind::Config)?;
+                if !changed {
+                    reporter.info(&format!("`{}` isn't set in {}", key, path.display()));
+                }
+                Ok(ConfigOutput::Changed(Changed {
+                    path,
+                    key,
+                    value: None,
+                    changed,
+                }))
+            }
+        }
+    }
+}
+
+impl Config {
+    /// Opens the global config file in an editor
+    fn edit(&self, opts: &GlobalOpts, reporter: &dyn Reporter) -> Result<Edited> {
+        let mut output = Edited {
+            path: CONFIG_PATH.clone(),
+            saved: false,
+            rejected: None,
+        };
         if self.print_path {
-            TERM_OUT.write_line(&format!("{}", CONFIG_PATH.display()))?;
-            return Ok(());
+            if !opts.json() {
+                TERM_OUT.write_line(&format!("{}", CONFIG_PATH.display()))?;
+            }
+            return Ok(output);
+        }
+        if opts.non_interactive {
+            return Err(anyhow!("Can't open an editor when running non-interactively, use `--print-path` to locate the config file instead"))
+                .category(ErrorKind::Input);
         }
         cfg_if! {
             if #[cfg(windows)] {
-                if verbose {
-                    TERM_ERR.write_line("Interactive edit disabled because default editor is notepad")?;
-                }
+                reporter.detail("Interactive edit disabled because default editor is notepad");
                 if env::var_os("EDITOR").is_none() && env::var_os("VISUAL").is_none() {
-                    TERM_OUT.write_line(&format!("{}", CONFIG_PATH.display()))?;
-                    return Ok(());
+                    if !opts.json() {
+                        TERM_OUT.write_line(&format!("{}", CONFIG_PATH.display()))?;
+                    }
+                    return Ok(output);
                 }
             }
         }
 
-        let p = ProgressBar::new_spinner();
-        p.set_message("Editing config");
-        p.enable_steady_tick(100);
+        reporter.step("Editing config");
 
-        let config = serde_json::to_string_pretty(&AppConfig::read()?)?;
-        if let Some(s) = Editor::new().extension(".json").edit(&config)? {
-            let config: AppConfig = serde_json::from_str(&s).context("Invalid config file")?;
-            config.write()?;
+        let mut text =
+            serde_json::to_string_pretty(&AppConfig::read_global().category(ErrorKind::Config)?)?;
+        // Reopens the editor with the invalid text until it's fixed or the user gives up
+        loop {
+            text = match Editor::new().extension(".json").edit(&text)? {
+                Some(s) => s,
+                None => break,
+            };
+            let e = match config::parse(&text) {
+                Ok(config) => {
+                    config.write()?;
+                    output.saved = true;
+                    reporter.finish(Some("Changes saved"));
+                    return Ok(output);
+                }
+                Err(e) => e,
+            };
 
-            p.finish_with_message("Changes saved");
-        } else {
-            p.finish_with_message("Changes discarded");
+            reporter.clear();
+            TERM_ERR.write_line(&format!("{:#}", e))?;
+            let rejected = rej_path();
+            let choice = Select::new()
+                .with_prompt("The config is invalid")
+                .items(&[
+                    "Edit again".to_owned(),
+                    format!("Save changes to {}", rejected.display()),
+                    "Discard changes".to_owned(),
+                ])
+                .default(0)
+                .interact_on(&*TERM_ERR)?;
+            match choice {
+                0 => continue,
+                1 => {
+                    fs::write(&rejected, &text)
+                        .with_context(|| format!("Can't save changes to {}", rejected.display()))?;
+                    reporter.finish(Some(&format!(
+                        "Invalid changes saved to {}",
+                        rejected.display()
+                    )));
+                    output.rejected = Some(rejected);
+                    return Ok(output);
+                }
+                _ => break,
+            }
         }
 
-        Ok(())
+        reporter.finish(Some("Changes discarded"));
+        Ok(output)
     }
 }
@@ -0,0 +1,81 @@
+use crate::{
+    commands::{GlobalOpts, Run},
+    terminal::TERM_OUT,
+};
+use anyhow::{anyhow, Result};
+use bm2::{graph, progress::Reporter, resolve};
+use std::{path::PathBuf, str::FromStr};
+use structopt::StructOpt;
+
+/// Graph command options
+#[derive(StructOpt, Debug)]
+pub struct Graph {
+    /// Manifests, registry snapshots or directories to look for manifests in
+    #[structopt(name = "PATH", default_value = ".")]
+    paths: Vec<PathBuf>,
+
+    /// Output format
+    #[structopt(
+        short,
+        long,
+        name = "FORMAT",
+        default_value = "dot",
+        possible_values = &["dot", "mermaid", "json"]
+    )]
+    format: GraphFormat,
+
+    /// Only shows the mods reachable from this one, can be repeated
+    #[structopt(short, long, name = "ID", number_of_values = 1)]
+    root: Vec<String>,
+}
+
+/// Graph output format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+    /// Nodes and edges as JSON
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dot" => Ok(Self::Dot),
+            "mermaid" => Ok(Self::Mermaid),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!("Unknown graph format `{}`", s)),
+        }
+    }
+}
+
+impl Run for Graph {
+    type Output = graph::Graph;
+
+    fn run(self, opts: &GlobalOpts, reporter: &dyn Reporter) -> Result<graph::Graph> {
+        reporter.step("Reading manifests");
+        let mut manifests = Vec::new();
+        for path in &self.paths {
+            manifests.extend(resolve::read_manifests(path)?);
+        }
+        reporter.finish(Some(&format!("Found {} manifests", manifests.len())));
+
+        let graph = graph::Graph::new(manifests, &self.root)?;
+        for problem in &graph.problems {
+            reporter.info(problem);
+        }
+        if !opts.json() {
+            let rendered = match self.format {
+                GraphFormat::Dot => graph.to_dot(),
+                GraphFormat::Mermaid => graph.to_mermaid(),
+                GraphFormat::Json => serde_json::to_string_pretty(&graph)? + "\n",
+            };
+            TERM_OUT.write_str(&rendered)?;
+        }
+        Ok(graph)
+    }
+}
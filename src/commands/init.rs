@@ -1,9 +1,27 @@
-use crate::{commands::Run, config::Config, globals::TERM_ERR, utils};
-use anyhow::Result;
-use dialoguer::Input;
-use manifest::{Manifest, SCHEMA};
-use semver::Version;
-use std::{fs::File, path::PathBuf};
+use crate::{
+    commands::{GlobalOpts, Run},
+    terminal::TERM_ERR,
+    utils,
+};
+use anyhow::{anyhow, Context, Result};
+use bm2::{
+    config::Config,
+    detect::{self, Detected},
+    error::{ErrorKind, ResultExt},
+    progress::Reporter,
+    template,
+};
+use dialoguer::{Input, Select};
+use manifest::{Links, Manifest, Publish, SCHEMA};
+use reqwest::Url;
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    iter::FromIterator,
+    path::{Path, PathBuf},
+};
 use structopt::StructOpt;
 
 /// Init command options
@@ -21,6 +39,10 @@ pub struct Init {
     #[structopt(long, name = "NAME")]
     name: Option<String>,
 
+    /// Version, 0.1.0 by default
+    #[structopt(long, name = "MOD_VERSION")]
+    mod_version: Option<Version>,
+
     /// Game version
     #[structopt(long, name = "VERSION")]
     game_version: Option<String>,
@@ -29,6 +51,15 @@ pub struct Init {
     #[structopt(long, name = "DESCRIPTION")]
     description: Option<String>,
 
+    /// Description line, repeat for a multi-line description
+    #[structopt(
+        long,
+        name = "LINE",
+        conflicts_with = "DESCRIPTION",
+        number_of_values = 1
+    )]
+    description_line: Vec<String>,
+
     /// Author
     #[structopt(long, name = "AUTHOR")]
     author: Option<String>,
@@ -36,73 +67,285 @@ pub struct Init {
     /// License
     #[structopt(long, name = "LICENSE")]
     license: Option<String>,
+
+    /// Dependency as `Id@requirement`, like `BSIPA@^4.0`, can be repeated
+    #[structopt(long, name = "DEPENDENCY", parse(try_from_str = parse_requirement), number_of_values = 1)]
+    depends_on: Vec<(String, VersionReq)>,
+
+    /// Conflicting mod as `Id@requirement`, can be repeated
+    #[structopt(long, name = "CONFLICT", parse(try_from_str = parse_requirement), number_of_values = 1)]
+    conflicts_with: Vec<(String, VersionReq)>,
+
+    /// ID of a mod to load after, can be repeated
+    #[structopt(long, name = "LOAD_AFTER", number_of_values = 1)]
+    load_after: Vec<String>,
+
+    /// ID of a mod to load before, can be repeated
+    #[structopt(long, name = "LOAD_BEFORE", number_of_values = 1)]
+    load_before: Vec<String>,
+
+    /// Feature, can be repeated
+    #[structopt(long, name = "FEATURE", number_of_values = 1)]
+    feature: Vec<String>,
+
+    /// Icon path
+    #[structopt(long, name = "ICON")]
+    icon: Option<PathBuf>,
+
+    /// Link as `kind=URL`, kind being `project-home`, `project-source` or `donate`, can be repeated
+    #[structopt(long, name = "LINK", parse(try_from_str = parse_link), number_of_values = 1)]
+    link: Vec<(LinkKind, Url)>,
+
+    /// Readme path
+    #[structopt(long, name = "README")]
+    readme: Option<PathBuf>,
+
+    /// Command run before publishing, can be repeated
+    #[structopt(long, name = "SCRIPT", number_of_values = 1)]
+    script: Vec<String>,
+
+    /// Resource to publish
+    #[structopt(long, name = "RESOURCE")]
+    resource: Option<PathBuf>,
+
+    /// Overwrites existing files
+    #[structopt(short, long)]
+    force: bool,
+
+    /// Infers missing fields from the C# project, license file and git repository next to the manifest
+    #[structopt(long)]
+    detect: bool,
+
+    /// Scaffolds a project around the manifest from a built-in template, like `bsipa`,
+    /// or a template directory in the bm2 config directory
+    #[structopt(long, name = "TEMPLATE")]
+    template: Option<String>,
+}
+
+/// Kind of manifest link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkKind {
+    ProjectHome,
+    ProjectSource,
+    Donate,
+}
+
+/// Parses an `Id@requirement` pair
+fn parse_requirement(s: &str) -> Result<(String, VersionReq)> {
+    let (id, req) = s
+        .split_once('@')
+        .with_context(|| format!("Invalid value `{}`, expected `Id@requirement`", s))?;
+    let req = VersionReq::parse(req)
+        .map_err(|e| anyhow!("Invalid version requirement `{}`: {}", req, e))?;
+    Ok((id.to_owned(), req))
+}
+
+/// Parses a `kind=URL` pair
+fn parse_link(s: &str) -> Result<(LinkKind, Url)> {
+    let (kind, url) = s
+        .split_once('=')
+        .with_context(|| format!("Invalid link `{}`, expected `kind=URL`", s))?;
+    let kind = match kind {
+        "project-home" => LinkKind::ProjectHome,
+        "project-source" => LinkKind::ProjectSource,
+        "donate" => LinkKind::Donate,
+        _ => {
+            return Err(anyhow!(
+                "Invalid link kind `{}`, expected `project-home`, `project-source` or `donate`",
+                kind
+            ))
+        }
+    };
+    let url = Url::parse(url).with_context(|| format!("Invalid link URL `{}`", url))?;
+    Ok((kind, url))
+}
+
+/// `None` for empty collections, as manifests leave them out
+fn non_empty<T, C: FromIterator<T>>(items: Vec<T>) -> Option<C> {
+    if items.is_empty() {
+        None
+    } else {
+        Some(items.into_iter().collect())
+    }
+}
+
+/// Full SPDX identifier of a detected GNU license, whose text doesn't say whether later versions can be used
+///
+/// The default license is used if it's one of the two, otherwise the user picks one,
+/// or it's left to the default when running non-interactively.
+fn gnu_license(
+    family: &str,
+    default: Option<&String>,
+    non_interactive: bool,
+) -> Result<Option<String>> {
+    let choices = [format!("{}-only", family), format!("{}-or-later", family)];
+    if let Some(d) = default.filter(|d| choices.contains(d)) {
+        return Ok(Some(d.clone()));
+    }
+    if non_interactive {
+        return Ok(None);
+    }
+    let choice = Select::new()
+        .with_prompt(&format!(
+            "The license file is {}, can later versions of it be used?",
+            family
+        ))
+        .items(&choices)
+        .default(0)
+        .interact_on(&*TERM_ERR)?;
+    Ok(Some(choices[choice].clone()))
+}
+
+/// Init command output
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InitOutput {
+    files: Vec<PathBuf>,
+    manifest: Manifest,
 }
 
 impl Run for Init {
-    fn run(self, verbose: bool) -> Result<()> {
-        let config = Config::read()?;
-
-        let id = self
-            .id
-            .unwrap_or(Input::new().with_prompt("ID").interact_on(&*TERM_ERR)?);
-        let name = self
-            .name
-            .unwrap_or(Input::new().with_prompt("Name").interact_on(&*TERM_ERR)?);
-        let game_version = Input::new()
-            .with_prompt("Game version")
-            .interact_on(&*TERM_ERR)?;
-        let description = self.description.unwrap_or(
+    type Output = InitOutput;
+
+    fn run(self, opts: &GlobalOpts, reporter: &dyn Reporter) -> Result<InitOutput> {
+        let config = Config::read().category(ErrorKind::Config)?;
+        let ni = opts.non_interactive;
+        if self.file.exists() && !self.force {
+            return Err(anyhow!(
+                "{} already exists, use `--force` to overwrite it",
+                self.file.display()
+            ))
+            .category(ErrorKind::Input);
+        }
+        let project_dir = match self.file.parent() {
+            Some(p) if p != Path::new("") => p.to_owned(),
+            _ => PathBuf::from("."),
+        };
+
+        let detected = if self.detect {
+            reporter.step("Detecting project information");
+            let detected = detect::detect(&project_dir).category(ErrorKind::Input)?;
+            for source in &detected.sources {
+                reporter.detail(&format!("Read {}", source.display()));
+            }
+            reporter.finish(None);
+            detected
+        } else {
+            Detected::default()
+        };
+
+        let id = utils::value_or_prompt(self.id.or(detected.id), "--id", ni, || {
+            Input::new().with_prompt("ID").interact_on(&*TERM_ERR)
+        })?;
+        let name = utils::value_or_prompt(self.name.or(detected.name), "--name", ni, || {
+            Input::new().with_prompt("Name").interact_on(&*TERM_ERR)
+        })?;
+        let game_version = utils::value_or_prompt(self.game_version, "--game-version", ni, || {
             Input::new()
-                .with_prompt("Description")
-                .interact_on(&*TERM_ERR)?,
-        );
-
-        let author = self.author.unwrap_or(
-            config
-                .defaults
-                .author
-                .unwrap_or(Input::new().with_prompt("Author").interact_on(&*TERM_ERR)?),
-        );
-        let license = self.license.unwrap_or(
-            config.defaults.license.unwrap_or(
-                Input::new()
-                    .with_prompt("License")
-                    .interact_on(&*TERM_ERR)?,
-            ),
-        );
+                .with_prompt("Game version")
+                .interact_on(&*TERM_ERR)
+        })?;
+        let description = if self.description_line.is_empty() {
+            vec![utils::value_or_prompt(
+                self.description.or(detected.description),
+                "--description",
+                ni,
+                || {
+                    Input::new()
+                        .with_prompt("Description")
+                        .interact_on(&*TERM_ERR)
+                },
+            )?]
+        } else {
+            self.description_line
+        };
+
+        let author = utils::value_or_prompt(
+            self.author.or(detected.author).or(config.defaults.author),
+            "--author",
+            ni,
+            || Input::new().with_prompt("Author").interact_on(&*TERM_ERR),
+        )?;
+        let detected_license = match (&self.license, detected.gnu_license) {
+            (None, Some(family)) => gnu_license(&family, config.defaults.license.as_ref(), ni)?,
+            _ => detected.license,
+        };
+        let license = utils::value_or_prompt(
+            self.license
+                .or(detected_license)
+                .or(config.defaults.license),
+            "--license",
+            ni,
+            || Input::new().with_prompt("License").interact_on(&*TERM_ERR),
+        )?;
+
+        let mut links = Links {
+            project_source: detected.project_source,
+            ..Default::default()
+        };
+        for (kind, url) in self.link {
+            match kind {
+                LinkKind::ProjectHome => links.project_home = Some(url),
+                LinkKind::ProjectSource => links.project_source = Some(url),
+                LinkKind::Donate => links.donate = Some(url),
+            }
+        }
 
         let mut manifest = Manifest {
             schema: SCHEMA.to_owned(),
             id,
             name,
-            version: Version {
+            version: self.mod_version.or(detected.version).unwrap_or(Version {
                 major: 0,
                 minor: 1,
                 patch: 0,
                 pre: vec![],
                 build: vec![],
-            },
+            }),
             game_version,
-            description: vec![description],
+            description,
             author,
             license,
-            depends_on: None,
-            conflicts_with: None,
-            load_after: None,
-            load_before: None,
-            features: None,
-            icon: None,
-            links: Default::default(),
-            publish: Default::default(),
-            readme: None,
+            depends_on: non_empty::<_, HashMap<_, _>>(self.depends_on),
+            conflicts_with: non_empty::<_, HashMap<_, _>>(self.conflicts_with),
+            load_after: non_empty::<_, HashSet<_>>(self.load_after),
+            load_before: non_empty::<_, HashSet<_>>(self.load_before),
+            features: non_empty::<_, HashSet<_>>(self.feature),
+            icon: self.icon,
+            links,
+            publish: Publish {
+                script: self.script,
+                resource: self.resource,
+            },
+            readme: self.readme,
+            misc: Default::default(),
         };
-        utils::edit_until_valid(&mut manifest)?;
+        utils::edit_until_valid(&mut manifest, ni)?;
 
-        if verbose {
-            TERM_ERR.write_line("Writing manifest...")?;
+        let mut files = Vec::new();
+        let file = self.file;
+        if let Some(name) = &self.template {
+            reporter.step(&format!("Scaffolding project from template `{}`", name));
+            let dir = file.parent().unwrap_or_else(|| Path::new(""));
+            let manifest_file = file.file_name().map(Path::new).unwrap_or(&file);
+            let vars = template::variables(&manifest, manifest_file);
+            let rendered = template::render_files(&template::load(name)?, &vars)?;
+            if rendered.iter().any(|f| dir.join(&f.path) == file) {
+                return Err(anyhow!(
+                    "Template `{}` contains the manifest file {}",
+                    name,
+                    file.display()
+                ))
+                .category(ErrorKind::Input);
+            }
+            files = template::write(&rendered, dir, self.force, reporter)?;
+            reporter.finish(Some(&format!("Created {} files", files.len())));
         }
-        let f = File::create(&self.file)?;
+
+        reporter.detail("Writing manifest...");
+        let f = File::create(&file)?;
         manifest.to_writer(f)?;
-        Ok(())
+        files.insert(0, file);
+        Ok(InitOutput { files, manifest })
     }
 }
@@ -1,50 +1,304 @@
-use crate::{commands::Run, globals::TERM_ERR, utils};
-use anyhow::{Context, Result};
+use crate::{
+    commands::{GlobalOpts, Run},
+    terminal::{TERM_ERR, TERM_OUT},
+    utils,
+};
+use anyhow::{anyhow, Context, Result};
+use bm2::{
+    error::{ErrorKind, ResultExt},
+    migrate,
+    progress::Reporter,
+};
+use console::style;
 use dialoguer::Input;
-use manifest::{Manifest, OldManifest};
+use manifest::{AnyManifest, Manifest, MigrationNote};
+use serde::Serialize;
 use std::{
-    fs::{self, File},
-    path::PathBuf,
+    fs,
+    path::{Path, PathBuf},
 };
 use structopt::StructOpt;
 
 /// Migrate command options
 #[derive(StructOpt, Debug)]
 pub struct Migrate {
-    /// File to migrate
-    #[structopt(name = "FILE", default_value = "manifest.json")]
-    file: PathBuf,
+    /// File to migrate, or directory to look for manifests in with `--recursive`
+    #[structopt(name = "PATH")]
+    path: Option<PathBuf>,
 
     /// License to use
     #[structopt(short, long, name = "LICENSE")]
     license: Option<String>,
+
+    /// Migrates every manifest.json in a directory and its subdirectories
+    #[structopt(short, long)]
+    recursive: bool,
+
+    /// Writes the migrated manifests with `--recursive`, which only shows what would change otherwise
+    #[structopt(short, long, requires = "recursive")]
+    write: bool,
+
+    /// Suffix of the old manifest backups
+    #[structopt(long, name = "SUFFIX", default_value = ".old")]
+    backup_suffix: String,
+
+    /// Doesn't back up old manifests
+    #[structopt(long, conflicts_with = "SUFFIX")]
+    no_backup: bool,
+}
+
+/// Migrate command output
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrateOutput {
+    /// Manifests written
+    files: Vec<PathBuf>,
+    manifests: Vec<MigratedManifest>,
+}
+
+/// Outcome of migrating a single manifest
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MigratedManifest {
+    path: PathBuf,
+    status: MigrationStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backup: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    manifest: Option<Manifest>,
+    /// What happened to the fields the new format doesn't define
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<MigrationNote>,
+    /// Why the previewed manifest isn't valid yet, which has to be fixed when writing it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    invalid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// What happened to a manifest
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum MigrationStatus {
+    /// Written in the new format
+    Migrated,
+    /// Only previewed
+    Previewed,
+    /// Already in the new format
+    Skipped,
+    /// Couldn't be migrated
+    Failed,
+}
+
+impl MigratedManifest {
+    fn new(path: PathBuf, status: MigrationStatus) -> Self {
+        Self {
+            path,
+            status,
+            backup: None,
+            diff: None,
+            manifest: None,
+            notes: Vec::new(),
+            invalid: None,
+            error: None,
+        }
+    }
+}
+
+/// Prints a unified diff, coloured when the terminal supports it
+fn print_diff(diff: &str) -> Result<()> {
+    for line in diff.lines() {
+        let line = if line.starts_with("+++") || line.starts_with("---") {
+            style(line).bold()
+        } else if line.starts_with('+') {
+            style(line).green()
+        } else if line.starts_with('-') {
+            style(line).red()
+        } else if line.starts_with("@@") {
+            style(line).cyan()
+        } else {
+            style(line)
+        };
+        TERM_OUT.write_line(&line.to_string())?;
+    }
+    Ok(())
+}
+
+impl Migrate {
+    /// Backup suffix, if backups are enabled
+    fn backup_suffix(&self) -> Option<&str> {
+        if self.no_backup {
+            None
+        } else {
+            Some(&self.backup_suffix)
+        }
+    }
+
+    /// Migrates a single manifest, writing it or only computing the diff
+    ///
+    /// The license is asked for once there's a manifest to migrate, and reused for the others.
+    fn migrate_file(
+        &self,
+        path: &Path,
+        license: &mut Option<String>,
+        write: bool,
+        opts: &GlobalOpts,
+        reporter: &dyn Reporter,
+    ) -> Result<MigratedManifest> {
+        reporter.detail(&format!("Reading {}...", path.display()));
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("Can't open {}", path.display()))?;
+        let old_manifest = match contents
+            .parse::<AnyManifest>()
+            .with_context(|| format!("Can't read {}", path.display()))
+            .category(ErrorKind::ManifestParse)?
+        {
+            AnyManifest::Old(m) => m,
+            AnyManifest::New(_) => {
+                reporter.info(&format!(
+                    "{} is already in the new format, skipping",
+                    path.display()
+                ));
+                return Ok(MigratedManifest::new(
+                    path.to_owned(),
+                    MigrationStatus::Skipped,
+                ));
+            }
+        };
+
+        let license = match (old_manifest.license(), &mut *license) {
+            (Some(l), _) => l.to_owned(),
+            (None, Some(l)) => l.clone(),
+            (None, None) => {
+                let prompt = if self.recursive {
+                    "SPDX identifier of the license for these mods"
+                } else {
+                    "SPDX identifier of the license for this mod"
+                };
+                let answer =
+                    utils::value_or_prompt(None, "--license", opts.non_interactive, || {
+                        Input::new().with_prompt(prompt).interact_on(&*TERM_ERR)
+                    })?;
+                license.insert(answer).clone()
+            }
+        };
+        let dir = match path.parent() {
+            Some(p) if p != Path::new("") => p,
+            _ => Path::new("."),
+        };
+        let (mut new_manifest, notes) = migrate::migrate(old_manifest, license, dir);
+        for note in &notes {
+            reporter.info(&format!("{}: {}", path.display(), note));
+        }
+        // Previews only report what needs fixing, so they never prompt
+        let invalid = if write {
+            utils::edit_until_valid(&mut new_manifest, opts.non_interactive)?;
+            None
+        } else {
+            new_manifest.validate().err().map(|e| e.to_string())
+        };
+        if let Some(e) = &invalid {
+            reporter.info(&format!(
+                "{}: {}, which has to be fixed when writing it",
+                path.display(),
+                e
+            ));
+        }
+        let diff = migrate::diff(path, &contents, &new_manifest.to_string()?);
+
+        let mut migrated = MigratedManifest::new(path.to_owned(), MigrationStatus::Previewed);
+        migrated.invalid = invalid;
+        if write {
+            migrated.backup =
+                migrate::write_migrated(path, &new_manifest, self.backup_suffix(), reporter)?;
+            migrated.status = MigrationStatus::Migrated;
+        }
+        migrated.diff = Some(diff);
+        migrated.manifest = Some(new_manifest);
+        migrated.notes = notes;
+        Ok(migrated)
+    }
 }
 
 impl Run for Migrate {
-    fn run(self, verbose: bool) -> Result<()> {
-        if verbose {
-            TERM_ERR.write_line("Reading old manifest...")?;
-        }
-        let f = File::open(&self.file).context("Can't open specified file")?;
-        let old_manifest = OldManifest::from_reader(f).context("Invalid manifest")?;
-        let license = self.license.unwrap_or(
-            Input::new()
-                .with_prompt("SPDX identifier of the license for this mod")
-                .interact_on(&*TERM_ERR)?,
-        );
-        let mut new_manifest = Manifest::from((old_manifest, license));
-        utils::edit_until_valid(&mut new_manifest)?;
-
-        if verbose {
-            TERM_ERR.write_line("Backing up old manifest")?;
-        }
-        let old_manifest_path = format!("{}.old", &self.file.display());
-        fs::rename(&self.file, old_manifest_path)?;
-        if verbose {
-            TERM_ERR.write_line("Writing new manifest...")?;
-        }
-        let f = File::create(&self.file)?;
-        new_manifest.to_writer(f)?;
-        Ok(())
+    type Output = MigrateOutput;
+
+    fn run(self, opts: &GlobalOpts, reporter: &dyn Reporter) -> Result<MigrateOutput> {
+        let (paths, write) = if self.recursive {
+            let dir = self.path.clone().unwrap_or_else(|| PathBuf::from("."));
+            reporter.step(&format!("Looking for manifests in {}", dir.display()));
+            let paths = migrate::find_manifests(&dir)
+                .with_context(|| format!("Can't read directory {}", dir.display()))
+                .category(ErrorKind::Input)?;
+            reporter.finish(Some(&format!("Found {} manifests", paths.len())));
+            (paths, self.write)
+        } else {
+            let file = self
+                .path
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(migrate::MANIFEST_NAME));
+            (vec![file], true)
+        };
+
+        let mut license = self.license.clone();
+        let mut manifests = Vec::with_capacity(paths.len());
+        let mut first_error = None;
+        for path in paths {
+            let result = self.migrate_file(&path, &mut license, write, opts, reporter);
+            match result {
+                Ok(migrated) => {
+                    if self.recursive && !opts.json() {
+                        if let Some(diff) = &migrated.diff {
+                            print_diff(diff)?;
+                        }
+                    }
+                    manifests.push(migrated);
+                }
+                Err(e) if self.recursive => {
+                    reporter.info(&format!("Couldn't migrate {}: {:#}", path.display(), e));
+                    let mut failed = MigratedManifest::new(path, MigrationStatus::Failed);
+                    failed.error = Some(format!("{:#}", e));
+                    manifests.push(failed);
+                    first_error.get_or_insert(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if self.recursive && !write {
+            let previewed = manifests
+                .iter()
+                .filter(|m| m.status == MigrationStatus::Previewed)
+                .count();
+            if previewed > 0 {
+                reporter.info(&format!(
+                    "{} manifests would be migrated, run again with `--write` to apply the changes",
+                    previewed
+                ));
+            }
+        }
+        if let Some(e) = first_error {
+            let failed = manifests
+                .iter()
+                .filter(|m| m.status == MigrationStatus::Failed)
+                .count();
+            return Err(anyhow!(
+                "{} of {} manifests couldn't be migrated",
+                failed,
+                manifests.len()
+            ))
+            .category(ErrorKind::of(&e));
+        }
+
+        Ok(MigrateOutput {
+            files: manifests
+                .iter()
+                .filter(|m| m.status == MigrationStatus::Migrated)
+                .map(|m| m.path.clone())
+                .collect(),
+            manifests,
+        })
     }
 }
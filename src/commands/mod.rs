@@ -1,25 +1,144 @@
 mod config;
+mod graph;
 mod init;
 mod migrate;
 mod publish;
 mod update;
 
-use crate::commands::{
-    config::Config, init::Init, migrate::Migrate, publish::Publish, update::Update,
+pub use crate::commands::update::update;
+
+use crate::{
+    commands::{
+        config::Config, graph::Graph, init::Init, migrate::Migrate, publish::Publish,
+        update::Update,
+    },
+    terminal::{JsonEvents, Plain, Spinner},
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use bm2::progress::{Reporter, Silent};
+use serde::Serialize;
+use serde_json::Value;
+use std::str::FromStr;
 use structopt::StructOpt;
 
+/// Output format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable text and progress bars
+    Text,
+    /// A single JSON object on stdout
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!("Unknown output format `{}`", s)),
+        }
+    }
+}
+
+/// Progress reporting style
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressStyle {
+    /// Interactive if stderr is a terminal and the output is text, plain otherwise
+    Auto,
+    /// Animated spinners
+    Interactive,
+    /// Timestamped lines
+    Plain,
+    /// A JSON object per line
+    Json,
+    /// Nothing at all
+    None,
+}
+
+impl FromStr for ProgressStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "interactive" => Ok(Self::Interactive),
+            "plain" => Ok(Self::Plain),
+            "json" => Ok(Self::Json),
+            "none" => Ok(Self::None),
+            _ => Err(anyhow!("Unknown progress style `{}`", s)),
+        }
+    }
+}
+
+/// Options shared by all commands
+#[derive(StructOpt, Debug)]
+pub struct GlobalOpts {
+    /// Prints more information
+    #[structopt(short, long)]
+    pub verbose: bool,
+
+    /// Never prompts, fails if a required value isn't provided (implied when stdin isn't a terminal)
+    #[structopt(short = "y", long, alias = "yes")]
+    pub non_interactive: bool,
+
+    /// Output format
+    #[structopt(
+        short,
+        long,
+        name = "FORMAT",
+        default_value = "text",
+        possible_values = &["text", "json"]
+    )]
+    pub output: OutputFormat,
+
+    /// Progress reporting style, written to stderr
+    #[structopt(
+        long,
+        name = "STYLE",
+        default_value = "auto",
+        possible_values = &["auto", "interactive", "plain", "json", "none"]
+    )]
+    pub progress: ProgressStyle,
+}
+
+impl GlobalOpts {
+    /// Whether the output is meant for machines
+    pub fn json(&self) -> bool {
+        self.output == OutputFormat::Json
+    }
+
+    /// Progress reporter matching the options
+    pub fn reporter(&self) -> Box<dyn Reporter> {
+        match self.progress {
+            ProgressStyle::Auto if self.json() || !atty::is(atty::Stream::Stderr) => {
+                Box::new(Plain::new(self.verbose))
+            }
+            ProgressStyle::Auto | ProgressStyle::Interactive => {
+                Box::new(Spinner::new(self.verbose))
+            }
+            ProgressStyle::Plain => Box::new(Plain::new(self.verbose)),
+            ProgressStyle::Json => Box::new(JsonEvents::new(self.verbose)),
+            ProgressStyle::None => Box::new(Silent),
+        }
+    }
+}
+
 /// Run function, the trait is not really needed but it's a nice convention
 pub trait Run {
+    /// Structured result of the command, printed in JSON output mode
+    type Output: Serialize;
+
     /// Runs the command
-    fn run(self, verbose: bool) -> Result<()>;
+    fn run(self, opts: &GlobalOpts, reporter: &dyn Reporter) -> Result<Self::Output>;
 }
 
 macro_rules! create_command {
     ($($name:ident : $doc:literal,)*) => {
         /// Available commands
         #[derive(StructOpt, Debug)]
+        #[allow(clippy::large_enum_variant)] // Parsed once per run
         pub enum Command {
             $(
                 #[doc=$doc]
@@ -27,11 +146,24 @@ macro_rules! create_command {
             )*
         }
 
+        impl Command {
+            /// Name of the command as typed on the command line
+            pub fn name(&self) -> String {
+                match self {
+                    $(
+                        Self::$name(_) => stringify!($name).to_lowercase(),
+                    )*
+                }
+            }
+        }
+
         impl Run for Command {
-            fn run(self, verbose: bool) -> Result<()> {
+            type Output = Value;
+
+            fn run(self, opts: &GlobalOpts, reporter: &dyn Reporter) -> Result<Value> {
                 match self {
                     $(
-                        Self::$name(c) => c.run(verbose),
+                        Self::$name(c) => Ok(serde_json::to_value(c.run(opts, reporter)?)?),
                     )*
                 }
             }
@@ -41,6 +173,7 @@ macro_rules! create_command {
 
 create_command!(
     Config: "Edits the application config",
+    Graph: "Exports the dependency graph of a set of mods",
     Init: "Initialises a new manifest",
     Migrate: "Migrates a manifest from the old to the new format",
     Publish: "Publishes this mod to BeatMods",
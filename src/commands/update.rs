@@ -1,13 +1,170 @@
-use crate::{commands::Run, updater};
+use crate::{
+    commands::{GlobalOpts, Run},
+    terminal::TERM_ERR,
+};
 use anyhow::Result;
+use bm2::{
+    config::Config,
+    error::{ErrorKind, ResultExt},
+    progress::Reporter,
+    updater::{self, AvailableUpdate, VERSION},
+};
+use dialoguer::Confirmation;
+use semver::Version;
+use serde::Serialize;
+use std::time::Duration;
 use structopt::StructOpt;
 
 /// Update command options
 #[derive(StructOpt, Debug)]
-pub struct Update {}
+pub struct Update {
+    /// Installs a specific version instead of the newest one, which allows downgrading
+    #[structopt(long, name = "VERSION")]
+    to: Option<Version>,
+
+    /// Only reports whether an update is available
+    #[structopt(long)]
+    check: bool,
+
+    /// Restores the version that was installed before the last update
+    #[structopt(long, conflicts_with_all = &["VERSION", "check"])]
+    rollback: bool,
+}
+
+/// Outcome of an update check
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateStatus {
+    pub current_version: String,
+    pub latest_version: Option<String>,
+    /// Whether the update was installed, in which case the process should exit promptly
+    pub installed: bool,
+    /// Version that was restored by a rollback, in which case the process should exit promptly
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rolled_back_to: Option<String>,
+}
+
+impl UpdateStatus {
+    fn new(available: Option<&AvailableUpdate>) -> Self {
+        Self {
+            current_version: VERSION.to_string(),
+            latest_version: available.map(|a| a.version.to_string()),
+            installed: false,
+            rolled_back_to: None,
+        }
+    }
+}
 
 impl Run for Update {
-    fn run(self, _verbose: bool) -> Result<()> {
-        updater::update(false)
+    type Output = UpdateStatus;
+
+    fn run(self, opts: &GlobalOpts, reporter: &dyn Reporter) -> Result<UpdateStatus> {
+        if self.rollback {
+            let version = updater::rollback(reporter).category(ErrorKind::Update)?;
+            if !opts.json() {
+                TERM_ERR.write_line(&format!(
+                    "Rolled back to version {} (was: {})",
+                    version, &*VERSION
+                ))?;
+            }
+            return Ok(UpdateStatus {
+                rolled_back_to: Some(version.to_string()),
+                ..UpdateStatus::new(None)
+            });
+        }
+
+        let config = Config::read().category(ErrorKind::Config)?;
+        let available = match &self.to {
+            Some(v) => {
+                updater::find(&config.update_source, v, reporter).category(ErrorKind::Update)?
+            }
+            None => {
+                match updater::check(
+                    &config.update_source,
+                    &config.update_channel,
+                    false,
+                    reporter,
+                )
+                .category(ErrorKind::Update)?
+                {
+                    Some(a) => a,
+                    None => {
+                        if !opts.json() {
+                            TERM_ERR.write_line(&format!("bm2 is up to date ({})", &*VERSION))?;
+                        }
+                        return Ok(UpdateStatus::new(None));
+                    }
+                }
+            }
+        };
+        let mut status = UpdateStatus::new(Some(&available));
+
+        if self.check {
+            if !opts.json() {
+                TERM_ERR.write_line(&format!(
+                    "Version {} is available (current: {})",
+                    available.version, &*VERSION
+                ))?;
+            }
+            return Ok(status);
+        }
+
+        let install = opts.non_interactive
+            || Confirmation::new()
+                .with_text(&format!(
+                    "Do you want to install version {}? (current: {})",
+                    available.version, &*VERSION
+                ))
+                .interact_on(&*TERM_ERR)?;
+        if install {
+            updater::install(&config.update_source, &available, false, reporter)
+                .category(ErrorKind::Update)?;
+            status.installed = true;
+        }
+        Ok(status)
+    }
+}
+
+/// Checks for updates on the configured channel at the configured interval and installs them, used for automatic updates
+pub fn update(config: &Config, opts: &GlobalOpts, reporter: &dyn Reporter) -> Result<UpdateStatus> {
+    let interval = Duration::from_secs(config.update_check_interval.saturating_mul(60 * 60));
+    let available = match updater::check_cached(
+        &config.update_source,
+        &config.update_channel,
+        interval,
+        true,
+        reporter,
+    )
+    .category(ErrorKind::Update)?
+    {
+        Some(a) => a,
+        None => return Ok(UpdateStatus::new(None)),
+    };
+    let mut status = UpdateStatus::new(Some(&available));
+    let new_version = &available.version;
+
+    let install = if opts.non_interactive {
+        TERM_ERR.write_line(&format!(
+            "A new version is available, run `bm2 update` to install it (current: {}, new: {})",
+            &*VERSION, new_version
+        ))?;
+        false
+    } else {
+        let install = Confirmation::new()
+            .with_text(&format!(
+                "A new version is available, do you want to update? (current: {}, new: {})",
+                &*VERSION, new_version
+            ))
+            .interact_on(&*TERM_ERR)?;
+        TERM_ERR.clear_last_lines(1)?;
+        install
+    };
+    if !install {
+        return Ok(status);
     }
+
+    updater::install(&config.update_source, &available, true, reporter)
+        .category(ErrorKind::Update)?;
+    status.installed = true;
+    Ok(status)
 }
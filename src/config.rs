@@ -1,37 +1,147 @@
-use crate::globals::CONFIG_PATH;
-use anyhow::Result;
+use crate::{
+    error::{ErrorKind, ResultExt},
+    globals::CONFIG_PATH,
+    updater::{UpdateChannel, UpdateSource},
+};
+use anyhow::{anyhow, Context, Result};
+use jsonschema::JSONSchema;
+use lazy_static::lazy_static;
+use schemars::{schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
-use std::fs::{self, File};
+use serde_json::{Map, Value};
+use std::{
+    env,
+    fmt::{self, Display},
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+/// Name of the per-project config file, looked up in the current directory and its parents
+pub const PROJECT_CONFIG_NAME: &str = ".bm2.json";
+/// Prefix of the environment variables overriding config values
+pub const ENV_PREFIX: &str = "BM2_";
+
+/// Current config format version
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Step upgrading a config to the next format version
+type Migration = fn(&mut Map<String, Value>);
+
+/// Steps upgrading a config to the next format version, the first one upgrading from version 1
+static MIGRATIONS: &[Migration] = &[
+    // Unversioned files point to the schema of the release that wrote them, which doesn't describe
+    // the versioned format, schemas of other origins are kept
+    |config| {
+        let custom = matches!(
+            config.get("$schema"),
+            Some(Value::String(s)) if !s.starts_with(SCHEMA_URL_PREFIX)
+        );
+        if !custom {
+            config.insert("$schema".to_owned(), Value::String(schema()));
+        }
+    },
+];
+
+/// Type of the value a config key holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    Bool,
+    Integer,
+    String,
+    /// Any JSON value, replaced as a whole when layering
+    Json,
+}
+
+/// Config keys that can be set individually, as dotted paths
+pub static KEYS: &[(&str, KeyKind)] = &[
+    ("autoUpdate", KeyKind::Bool),
+    ("updateChannel", KeyKind::String),
+    ("updateSource", KeyKind::Json),
+    ("updateCheckInterval", KeyKind::Integer),
+    ("defaults.author", KeyKind::String),
+    ("defaults.license", KeyKind::String),
+    ("credentials.username", KeyKind::String),
+    ("credentials.password", KeyKind::String),
+];
+
+/// Config keys holding secrets, hidden unless asked for explicitly
+static SECRET_KEYS: &[&str] = &["credentials.password", "updateSource.authorization"];
+/// Text printed in place of secrets
+const SECRET_MASK: &str = "********";
+
+lazy_static! {
+    /// Checked in schema of the config file, values are validated against
+    static ref SCHEMA: JSONSchema =
+        JSONSchema::compile(&serde_json::from_str(include_str!("../config.schema.json")).unwrap())
+            .unwrap();
+}
 
 fn is_default<T: Default + PartialEq>(arg: &T) -> bool {
     arg == &Default::default()
 }
+fn update_check_interval() -> u64 {
+    24
+}
+fn config_version() -> u32 {
+    CONFIG_VERSION
+}
+/// Start of the config schema URLs of bm2 releases, followed by the version
+const SCHEMA_URL_PREFIX: &str = "https://raw.githubusercontent.com/raftario/bm2/v";
 fn schema() -> String {
-    concat!(
-        "https://raw.githubusercontent.com/raftario/bm2/v",
-        env!("CARGO_PKG_VERSION"),
-        "/config.schema.json"
+    format!(
+        "{}{}/config.schema.json",
+        SCHEMA_URL_PREFIX,
+        env!("CARGO_PKG_VERSION")
     )
-    .to_owned()
 }
 
 /// bm2 config
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
+#[schemars(
+    title = "bm2 config",
+    description = "bm2 command line utility configuration file"
+)]
 pub struct Config {
     #[serde(default = "schema")]
     #[serde(rename = "$schema")]
+    #[schemars(skip)]
     schema: String,
 
+    /// Config format version, older files are upgraded when read
+    #[serde(default = "config_version")]
+    #[schemars(title = "Version")]
+    version: u32,
+
     /// Enable automatic updates
+    #[schemars(title = "Auto update")]
     pub auto_update: bool,
 
+    /// Releases followed by the updater
     #[serde(default)]
     #[serde(skip_serializing_if = "is_default")]
+    #[schemars(title = "Update channel")]
+    pub update_channel: UpdateChannel,
+
+    /// Where releases are looked up
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_default")]
+    #[schemars(title = "Update source")]
+    pub update_source: UpdateSource,
+
+    /// Hours between automatic update checks, 0 checks on every run
+    #[serde(default = "update_check_interval")]
+    #[schemars(title = "Update check interval")]
+    pub update_check_interval: u64,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_default")]
+    #[schemars(title = "Defaults")]
     pub defaults: Defaults,
 
     #[serde(default)]
     #[serde(skip_serializing_if = "is_default")]
+    #[schemars(title = "Credentials")]
     pub credentials: Credentials,
 }
 
@@ -39,7 +149,11 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             schema: schema(),
+            version: CONFIG_VERSION,
             auto_update: true,
+            update_channel: UpdateChannel::default(),
+            update_source: UpdateSource::default(),
+            update_check_interval: update_check_interval(),
             defaults: Defaults::default(),
             credentials: Credentials::default(),
         }
@@ -47,47 +161,672 @@ impl Default for Config {
 }
 
 impl Config {
-    /// Read config from disk or creates a default one if it doesn't exist
+    /// Reads the effective config, merging the global and project files and the environment
     pub fn read() -> Result<Self> {
+        Ok(Self::resolve()?.0)
+    }
+
+    /// Effective value of every key along with where it comes from
+    pub fn entries() -> Result<Vec<Entry>> {
+        Ok(Self::resolve()?.1)
+    }
+
+    /// Merges the config layers of the current environment
+    fn resolve() -> Result<(Self, Vec<Entry>)> {
+        // Validates the global file, creating it if needed, before layering what it actually contains
+        Self::read_global()?;
+        let mut layers = vec![Layer {
+            origin: Origin::Global {
+                path: CONFIG_PATH.clone(),
+            },
+            value: serde_json::from_reader(File::open(&*CONFIG_PATH)?)?,
+        }];
+        if let Some(path) = find_project_config(&env::current_dir()?) {
+            let f = File::open(&path)?;
+            let mut value = serde_json::from_reader(f)
+                .with_context(|| format!("Invalid project config file {}", path.display()))?;
+            migrate(&mut value)?;
+            layers.push(Layer {
+                origin: Origin::Project { path },
+                value,
+            });
+        }
+        layers.extend(env_layers(env::vars())?);
+        resolve(&layers)
+    }
+
+    /// Reads the global config file or creates a default one if it doesn't exist
+    ///
+    /// Files in an older format are upgraded and written back.
+    pub fn read_global() -> Result<Self> {
         if !CONFIG_PATH.exists() {
             let config = Self::default();
             config.write()?;
             return Ok(config);
         }
 
-        let f = File::open(&*CONFIG_PATH)?;
-        Ok(serde_json::from_reader(f)?)
+        let value = read_file(&CONFIG_PATH)?;
+        Ok(serde_json::from_value(value)?)
     }
 
     /// Writes config to disk
     pub fn write(&self) -> Result<()> {
-        let mut config_dir = CONFIG_PATH.clone();
-        config_dir.pop();
-        fs::create_dir_all(config_dir)?;
+        write_atomic(&CONFIG_PATH, &serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Effective value of a single key along with where it comes from
+    pub fn get(key: &str) -> Result<Entry> {
+        key_kind(key)?;
+        Ok(Self::entries()?.into_iter().find(|e| e.key == key).unwrap())
+    }
+}
+
+/// Parses the content of an edited global config file
+///
+/// Errors point to the invalid key, if any, and the position in the text.
+pub fn parse(text: &str) -> Result<Config> {
+    let mut de = serde_json::Deserializer::from_str(text);
+    let result = serde_path_to_error::deserialize(&mut de)
+        .map_err(|e| {
+            let path = e.path().to_string();
+            if path == "." || path == "?" {
+                anyhow!("Invalid config: {}", e.into_inner())
+            } else {
+                anyhow!("Invalid value for `{}`: {}", path, e.into_inner())
+            }
+        })
+        .and_then(|config| {
+            de.end().context("Invalid config")?;
+            Ok(config)
+        });
+    result.category(ErrorKind::Config)
+}
+
+/// Writes a file by renaming a temporary file next to it into place, so it's never left half written
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = path.parent().context("Invalid config path")?;
+    fs::create_dir_all(dir)?;
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    let tmp = path.with_file_name(name);
 
-        let f = File::create(&*CONFIG_PATH)?;
-        serde_json::to_writer_pretty(f, self)?;
-        Ok(())
+    let result = fs::write(&tmp, contents).and_then(|_| fs::rename(&tmp, path));
+    if result.is_err() {
+        fs::remove_file(&tmp).ok();
     }
+    Ok(result?)
+}
+
+/// Kind of a config key, failing for unknown keys
+pub fn key_kind(key: &str) -> Result<KeyKind> {
+    KEYS.iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, kind)| *kind)
+        .with_context(|| {
+            let keys: Vec<&str> = KEYS.iter().map(|(k, _)| *k).collect();
+            format!(
+                "Unknown config key `{}`, expected one of {}",
+                key,
+                keys.join(", ")
+            )
+        })
+        .category(ErrorKind::Input)
+}
+
+/// Project config file to edit, the nearest existing one or a new one in the current directory
+pub fn project_config_path() -> Result<PathBuf> {
+    let cwd = env::current_dir()?;
+    Ok(find_project_config(&cwd).unwrap_or_else(|| cwd.join(PROJECT_CONFIG_NAME)))
+}
+
+/// Reads a config file as JSON, or an empty object if it doesn't exist
+///
+/// The global config file is upgraded to the current format version and written back if needed.
+fn read_file(path: &Path) -> Result<Value> {
+    if !path.exists() {
+        return Ok(Value::Object(Map::new()));
+    }
+    let f = File::open(path)?;
+    let mut value = serde_json::from_reader(f)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+    if path == *CONFIG_PATH && migrate(&mut value)? {
+        write_atomic(path, &serde_json::to_vec_pretty(&value)?)?;
+    }
+    Ok(value)
+}
+
+/// Upgrades a config to the current format version, returning whether it changed
+///
+/// Files without a version are version 1.
+fn migrate(value: &mut Value) -> Result<bool> {
+    migrate_with(value, MIGRATIONS)
+}
+
+/// Upgrades a config with the given steps, the current version being the one they upgrade to
+fn migrate_with(value: &mut Value, migrations: &[Migration]) -> Result<bool> {
+    let current = migrations.len() as u64 + 1;
+    let config = value
+        .as_object_mut()
+        .context("Invalid config, expected an object")
+        .category(ErrorKind::Config)?;
+    let version = match config.get("version") {
+        None => 1,
+        Some(v) => v
+            .as_u64()
+            .filter(|v| *v >= 1)
+            .with_context(|| format!("Invalid config version `{}`", v))
+            .category(ErrorKind::Config)?,
+    };
+    if version > current {
+        return Err(anyhow!(
+            "Config version {} is newer than the supported version {}, update bm2 to use it",
+            version,
+            current
+        ))
+        .category(ErrorKind::Config);
+    }
+    if version == current {
+        return Ok(false);
+    }
+
+    for step in &migrations[version as usize - 1..] {
+        step(config);
+    }
+    config.insert("version".to_owned(), current.into());
+    Ok(true)
+}
+
+/// JSON schema of the config file, as checked in at the root of the repository
+pub fn json_schema() -> String {
+    let mut schema = serde_json::to_string_pretty(&schema_for!(Config)).unwrap();
+    schema.push('\n');
+    schema
+}
+
+/// Validates a config file's content then writes it
+///
+/// The global config file has to be a complete config, other files only need valid values.
+fn write_file(path: &Path, value: &Value) -> Result<()> {
+    if path == *CONFIG_PATH {
+        serde_json::from_value::<Config>(value.clone())
+            .with_context(|| format!("Change would leave {} invalid", path.display()))
+            .category(ErrorKind::Input)?;
+    } else {
+        resolve(&[Layer {
+            origin: Origin::Project {
+                path: path.to_owned(),
+            },
+            value: value.clone(),
+        }])?;
+    }
+    write_atomic(path, &serde_json::to_vec_pretty(value)?)
+}
+
+/// Checks a value against the config schema, as if it was set in the default config
+fn check_schema(key: &str, value: &Value) -> Result<()> {
+    let mut config = serde_json::to_value(Config::default())?;
+    set(&mut config, key, value.clone());
+    if let Err(errors) = SCHEMA.validate(&config) {
+        let errors: Vec<String> = errors.map(|e| e.to_string()).collect();
+        return Err(anyhow!("{}", errors.join(", ")));
+    }
+    Ok(())
+}
+
+/// Parses and sets a key in a config file, returning the parsed value
+///
+/// Values are checked against the config schema, then against the config format for what it can't express.
+pub fn set_in_file(path: &Path, key: &str, raw: &str) -> Result<Value> {
+    let parsed = parse_value(key_kind(key)?, raw)
+        .and_then(|v| {
+            check_schema(key, &v)?;
+            let mut layer = Value::Object(Map::new());
+            set(&mut layer, key, v.clone());
+            resolve(&[Layer {
+                origin: Origin::Default,
+                value: layer,
+            }])?;
+            Ok(v)
+        })
+        .with_context(|| format!("Invalid value for config key `{}`", key))
+        .category(ErrorKind::Input)?;
+    let mut value = read_file(path)?;
+    set(&mut value, key, parsed.clone());
+    write_file(path, &value)?;
+    Ok(parsed)
+}
+
+/// Removes a key from a config file, along with the objects it leaves empty, returning whether it was set
+pub fn unset_in_file(path: &Path, key: &str) -> Result<bool> {
+    key_kind(key)?;
+    let mut value = read_file(path)?;
+    if !remove(&mut value, key) {
+        return Ok(false);
+    }
+    write_file(path, &value)?;
+    Ok(true)
+}
+
+/// Where a config value comes from
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "layer", rename_all = "camelCase")]
+pub enum Origin {
+    Default,
+    Global { path: PathBuf },
+    Project { path: PathBuf },
+    Env { var: String },
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::Global { path } => write!(f, "global config {}", path.display()),
+            Self::Project { path } => write!(f, "project config {}", path.display()),
+            Self::Env { var } => write!(f, "environment variable {}", var),
+        }
+    }
+}
+
+/// Effective value of a config key
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub key: &'static str,
+    pub value: Value,
+    pub origin: Origin,
+}
+
+/// Partial config from a single source
+#[derive(Debug)]
+struct Layer {
+    origin: Origin,
+    value: Value,
+}
+
+/// Value at a dotted path
+fn lookup<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.').try_fold(value, |v, k| v.get(k))
+}
+
+/// Sets the value at a dotted path, creating intermediate objects
+fn set(value: &mut Value, key: &str, new: Value) {
+    let mut current = value;
+    for k in key.split('.') {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        current = current
+            .as_object_mut()
+            .unwrap()
+            .entry(k)
+            .or_insert(Value::Null);
+    }
+    *current = new;
+}
+
+/// Removes the value at a dotted path along with the objects it leaves empty, returning whether it was set
+fn remove(value: &mut Value, key: &str) -> bool {
+    let object = match value.as_object_mut() {
+        Some(o) => o,
+        None => return false,
+    };
+    match key.split_once('.') {
+        None => object.remove(key).is_some(),
+        Some((first, rest)) => {
+            let removed = match object.get_mut(first) {
+                Some(v) => remove(v, rest),
+                None => false,
+            };
+            if removed && matches!(object[first].as_object(), Some(o) if o.is_empty()) {
+                object.remove(first);
+            }
+            removed
+        }
+    }
+}
+
+/// Replaces the secrets in the value of a key with a mask, `key` being empty for the whole config
+pub fn mask_secrets(key: &str, value: &mut Value) {
+    for secret in SECRET_KEYS {
+        let path = if key.is_empty() {
+            Some(*secret)
+        } else if *secret == key {
+            Some("")
+        } else {
+            secret.strip_prefix(key).and_then(|p| p.strip_prefix('.'))
+        };
+        let target = path.and_then(|p| {
+            p.split('.')
+                .filter(|k| !k.is_empty())
+                .try_fold(&mut *value, |v, k| v.get_mut(k))
+        });
+        if let Some(v) = target.filter(|v| !v.is_null()) {
+            *v = Value::String(SECRET_MASK.to_owned());
+        }
+    }
+}
+
+/// Environment variable overriding a key, `defaults.author` is `BM2_DEFAULTS__AUTHOR`
+pub fn env_var(key: &str) -> String {
+    let mut var = ENV_PREFIX.to_owned();
+    for (i, part) in key.split('.').enumerate() {
+        if i > 0 {
+            var.push_str("__");
+        }
+        for c in part.chars() {
+            if c.is_ascii_uppercase() {
+                var.push('_');
+            }
+            var.push(c.to_ascii_uppercase());
+        }
+    }
+    var
+}
+
+/// Parses a string into a value of the given kind
+pub fn parse_value(kind: KeyKind, s: &str) -> Result<Value> {
+    Ok(match kind {
+        KeyKind::Bool => Value::Bool(
+            s.parse()
+                .map_err(|_| anyhow!("expected `true` or `false`"))?,
+        ),
+        KeyKind::Integer => Value::from(
+            s.parse::<u64>()
+                .map_err(|_| anyhow!("expected a positive integer"))?,
+        ),
+        KeyKind::String => Value::String(s.to_owned()),
+        KeyKind::Json => serde_json::from_str(s).context("expected JSON")?,
+    })
+}
+
+/// Layers set by the environment variables for known keys, each overriding a single key
+fn env_layers<I: IntoIterator<Item = (String, String)>>(vars: I) -> Result<Vec<Layer>> {
+    let mut layers = Vec::new();
+    for (var, raw) in vars {
+        if let Some((key, kind)) = KEYS.iter().find(|(k, _)| env_var(k) == var) {
+            let mut value = Value::Object(Map::new());
+            let parsed = parse_value(*kind, &raw)
+                .with_context(|| format!("Invalid value for environment variable {}", var))?;
+            set(&mut value, key, parsed);
+            layers.push(Layer {
+                origin: Origin::Env { var },
+                value,
+            });
+        }
+    }
+    Ok(layers)
+}
+
+/// Finds the project config file in the directory or its parents
+fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join(PROJECT_CONFIG_NAME))
+        .find(|p| p.is_file())
+}
+
+/// Merges layers by increasing precedence, key by key
+fn resolve(layers: &[Layer]) -> Result<(Config, Vec<Entry>)> {
+    let mut defaults = serde_json::to_value(Config::default())?;
+    // Default values are skipped when serialising these
+    set(
+        &mut defaults,
+        "updateChannel",
+        serde_json::to_value(UpdateChannel::default())?,
+    );
+    set(
+        &mut defaults,
+        "updateSource",
+        serde_json::to_value(UpdateSource::default())?,
+    );
+    let mut merged = defaults.clone();
+    let mut entries = Vec::with_capacity(KEYS.len());
+    for (key, _) in KEYS {
+        let entry = layers
+            .iter()
+            .rev()
+            .find_map(|l| {
+                lookup(&l.value, key).map(|v| Entry {
+                    key,
+                    value: v.clone(),
+                    origin: l.origin.clone(),
+                })
+            })
+            .unwrap_or_else(|| Entry {
+                key,
+                value: lookup(&defaults, key).cloned().unwrap_or(Value::Null),
+                origin: Origin::Default,
+            });
+        if !entry.value.is_null() {
+            set(&mut merged, key, entry.value.clone());
+        }
+        entries.push(entry);
+    }
+
+    let config = serde_json::from_value(merged).context("Invalid config")?;
+    Ok((config, entries))
 }
 
 /// Default values for manifest initialisation
-#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct Defaults {
+    /// Author of new manifests
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(title = "Author")]
     pub author: Option<String>,
 
+    /// License of new manifests
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(title = "License")]
     pub license: Option<String>,
 }
 
 /// BeatMods1 credentials (legacy)
-#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, PartialEq, Eq)]
 pub struct Credentials {
+    /// BeatMods1 username
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(title = "Username")]
     pub username: Option<String>,
 
+    /// BeatMods1 password
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(title = "Password")]
     pub password: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{self, Config, Layer, Origin, CONFIG_VERSION, MIGRATIONS};
+    use serde_json::{json, Value};
+    use std::{env, fs, path::PathBuf};
+
+    #[test]
+    fn schema_up_to_date() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("config.schema.json");
+        let generated = config::json_schema();
+        if env::var_os("BM2_WRITE_SCHEMA").is_some() {
+            fs::write(&path, &generated).unwrap();
+        }
+        let checked_in = fs::read_to_string(&path).unwrap().replace("\r\n", "\n");
+        assert!(
+            checked_in == generated,
+            "config.schema.json is out of date, regenerate it with `BM2_WRITE_SCHEMA=1 cargo test`"
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let config =
+            config::parse(r#"{ "autoUpdate": false, "defaults": { "author": "raftario" } }"#);
+        assert_eq!(config.unwrap().defaults.author.as_deref(), Some("raftario"));
+
+        let error = |text: &str| format!("{:#}", config::parse(text).unwrap_err());
+        assert_eq!(
+            error("{\n  \"autoUpdate\": false,\n  \"defaults\": { \"author\": 1 }\n}"),
+            "Invalid value for `defaults.author`: invalid type: integer `1`, expected a string at line 3 column 27"
+        );
+        assert!(
+            error(r#"{ "autoUpdate": false, "updateChannel": "sometimes" }"#).starts_with(
+                "Invalid value for `updateChannel`: Invalid update channel `sometimes`"
+            )
+        );
+        assert_eq!(
+            error("{ \"autoUpdate\": false,\n}"),
+            "Invalid config: trailing comma at line 2 column 1"
+        );
+        assert!(error(r#"{ "autoUpdate": false } }"#)
+            .contains("trailing characters at line 1 column 25"));
+    }
+
+    #[test]
+    fn migrations() {
+        assert_eq!(MIGRATIONS.len() as u32, CONFIG_VERSION - 1);
+
+        let mut value = json!({
+            "$schema": "https://raw.githubusercontent.com/raftario/bm2/v0.1.0/config.schema.json",
+            "autoUpdate": false,
+            "defaults": { "author": "raftario" }
+        });
+        assert!(config::migrate(&mut value).unwrap());
+        assert!(!config::migrate(&mut value).unwrap());
+        let config: Config = serde_json::from_value(value).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.schema, config::schema());
+        assert!(!config.auto_update);
+        assert_eq!(config.defaults.author.as_deref(), Some("raftario"));
+
+        let mut custom = json!({ "$schema": "./config.schema.json", "autoUpdate": true });
+        assert!(config::migrate(&mut custom).unwrap());
+        assert_eq!(custom["$schema"], "./config.schema.json");
+        assert_eq!(custom["version"], CONFIG_VERSION);
+
+        let mut newer = json!({ "version": CONFIG_VERSION + 1, "autoUpdate": true });
+        assert!(config::migrate(&mut newer).is_err());
+        assert!(config::migrate(&mut json!({ "version": 0 })).is_err());
+    }
+
+    #[test]
+    fn renaming_migration() {
+        let mut migrations = MIGRATIONS.to_vec();
+        migrations.push(|config| {
+            if let Some(author) = config.remove("author") {
+                let defaults = config.entry("defaults").or_insert_with(|| json!({}));
+                defaults["author"] = author;
+            }
+        });
+
+        let mut value = json!({ "autoUpdate": true, "author": "raftario" });
+        assert!(config::migrate_with(&mut value, &migrations).unwrap());
+        assert_eq!(value["version"], CONFIG_VERSION + 1);
+        assert_eq!(value["defaults"]["author"], "raftario");
+        assert!(value.get("author").is_none());
+
+        // Files already in the previous version only go through the new step
+        let mut value = json!({ "version": CONFIG_VERSION, "author": "raftario" });
+        assert!(config::migrate_with(&mut value, &migrations).unwrap());
+        assert!(value.get("$schema").is_none());
+        assert_eq!(value["defaults"]["author"], "raftario");
+    }
+
+    #[test]
+    fn env_vars() {
+        assert_eq!(config::env_var("autoUpdate"), "BM2_AUTO_UPDATE");
+        assert_eq!(config::env_var("defaults.author"), "BM2_DEFAULTS__AUTHOR");
+
+        let vars = vec![
+            ("BM2_UPDATE_CHECK_INTERVAL".to_owned(), "0".to_owned()),
+            ("BM2_UNRELATED".to_owned(), "value".to_owned()),
+        ];
+        assert_eq!(config::env_layers(vars).unwrap().len(), 1);
+        let invalid = vec![("BM2_AUTO_UPDATE".to_owned(), "yes".to_owned())];
+        assert!(config::env_layers(invalid).is_err());
+    }
+
+    #[test]
+    fn secrets() {
+        let mut config = json!({
+            "updateSource": { "type": "github", "url": "https://example.com", "authorization": "token" },
+            "credentials": { "username": "raftario", "password": "hunter2" },
+        });
+        config::mask_secrets("", &mut config);
+        assert_eq!(config["updateSource"]["authorization"], "********");
+        assert_eq!(config["credentials"]["username"], "raftario");
+        assert_eq!(config["credentials"]["password"], "********");
+
+        let mut password = json!("hunter2");
+        config::mask_secrets("credentials.password", &mut password);
+        assert_eq!(password, "********");
+        let mut source = json!({ "type": "github", "url": "https://example.com" });
+        config::mask_secrets("updateSource", &mut source);
+        assert!(source.get("authorization").is_none());
+        let mut unset = Value::Null;
+        config::mask_secrets("credentials.password", &mut unset);
+        assert!(unset.is_null());
+    }
+
+    #[test]
+    fn set_and_unset() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".bm2.json");
+
+        config::set_in_file(&path, "defaults.author", "raftario").unwrap();
+        config::set_in_file(&path, "updateCheckInterval", "6").unwrap();
+        assert!(config::set_in_file(&path, "updateCheckInterval", "-1").is_err());
+        assert!(config::set_in_file(&path, "updateChannel", "sometimes").is_err());
+        assert!(config::set_in_file(&path, "defaults.name", "bm2").is_err());
+        let error =
+            config::set_in_file(&path, "updateSource", r#"{ "type": "index" }"#).unwrap_err();
+        assert!(format!("{:#}", error).contains("is not valid under any of the schemas"));
+        let written: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(
+            written,
+            json!({ "defaults": { "author": "raftario" }, "updateCheckInterval": 6 })
+        );
+
+        assert!(config::unset_in_file(&path, "defaults.author").unwrap());
+        assert!(!config::unset_in_file(&path, "defaults.license").unwrap());
+        let written: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written, json!({ "updateCheckInterval": 6 }));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn layering() {
+        let global = Origin::Global {
+            path: PathBuf::from("config.json"),
+        };
+        let project = Origin::Project {
+            path: PathBuf::from(".bm2.json"),
+        };
+        let layers = vec![
+            Layer {
+                origin: global.clone(),
+                value: json!({ "autoUpdate": true, "defaults": { "author": "raftario", "license": "MIT" } }),
+            },
+            Layer {
+                origin: project.clone(),
+                value: json!({ "defaults": { "license": "GPL-3.0" }, "updateCheckInterval": 1 }),
+            },
+        ];
+        let env = config::env_layers(vec![("BM2_AUTO_UPDATE".to_owned(), "false".to_owned())]);
+        let layers: Vec<Layer> = layers.into_iter().chain(env.unwrap()).collect();
+
+        let (config, entries) = config::resolve(&layers).unwrap();
+        assert!(!config.auto_update);
+        assert_eq!(config.update_check_interval, 1);
+        assert_eq!(config.defaults.author.as_deref(), Some("raftario"));
+        assert_eq!(config.defaults.license.as_deref(), Some("GPL-3.0"));
+
+        let origin = |key: &str| &entries.iter().find(|e| e.key == key).unwrap().origin;
+        assert_eq!(
+            origin("autoUpdate"),
+            &Origin::Env {
+                var: "BM2_AUTO_UPDATE".to_owned()
+            }
+        );
+        assert_eq!(origin("defaults.author"), &global);
+        assert_eq!(origin("defaults.license"), &project);
+        assert_eq!(origin("updateChannel"), &Origin::Default);
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    error::Error as StdError,
+    fmt::{self, Display, Formatter},
+};
+
+/// Error categories, each with a stable exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Uncategorised error
+    Other,
+    /// Missing or invalid command line input
+    Input,
+    /// Unreadable or invalid config file
+    Config,
+    /// Unreadable or unparsable manifest
+    ManifestParse,
+    /// Manifest that doesn't follow the schema
+    Validation,
+    /// Failed publish script command
+    Script,
+    /// Missing or unreadable resource
+    Packaging,
+    /// Rejected credentials
+    Auth,
+    /// Failed network request
+    Network,
+    /// Request rejected by the server
+    ServerRejection,
+    /// Failed self update
+    Update,
+    /// Self update installed instead of running the command
+    Updated,
+}
+
+impl ErrorKind {
+    /// Process exit code
+    pub fn code(self) -> i32 {
+        match self {
+            Self::Other => 1,
+            Self::Input => 2,
+            Self::Config => 3,
+            Self::ManifestParse => 10,
+            Self::Validation => 11,
+            Self::Script => 12,
+            Self::Packaging => 13,
+            Self::Auth => 20,
+            Self::Network => 21,
+            Self::ServerRejection => 22,
+            Self::Update => 30,
+            Self::Updated => 31,
+        }
+    }
+
+    /// Short ID included in error messages
+    pub fn id(self) -> String {
+        format!("E{:02}", self.code())
+    }
+
+    /// Category of an error, the innermost tag wins and untagged network errors are detected
+    pub fn of(e: &anyhow::Error) -> Self {
+        e.chain()
+            .filter_map(|c| c.downcast_ref::<Categorised>())
+            .last()
+            .map(|c| c.kind)
+            .or_else(|| {
+                if e.chain().any(|c| c.is::<reqwest::Error>()) {
+                    Some(Self::Network)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(Self::Other)
+    }
+}
+
+/// Error tagged with a category, transparent otherwise
+#[derive(Debug)]
+pub struct Categorised {
+    kind: ErrorKind,
+    inner: anyhow::Error,
+}
+
+impl Display for Categorised {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.inner, f)
+    }
+}
+
+impl StdError for Categorised {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.inner.source()
+    }
+}
+
+/// Extension trait to categorise errors
+pub trait ResultExt<T> {
+    /// Tags the error with a category
+    fn category(self, kind: ErrorKind) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> ResultExt<T> for Result<T, E> {
+    fn category(self, kind: ErrorKind) -> anyhow::Result<T> {
+        self.map_err(|e| {
+            let inner = e.into();
+            // Keep the most specific category
+            if inner.is::<Categorised>() {
+                return inner;
+            }
+            Categorised { kind, inner }.into()
+        })
+    }
+}
@@ -1,16 +1,34 @@
-use console::Term;
 use lazy_static::lazy_static;
 use std::path::PathBuf;
 
 pub const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
 lazy_static! {
-    pub static ref TERM_OUT: Term = Term::stdout();
-    pub static ref TERM_ERR: Term = Term::stderr();
     pub static ref CONFIG_PATH: PathBuf = {
         let mut cp = dirs::config_dir().unwrap();
         cp.push("bm2");
         cp.push("config.json");
         cp
     };
+    /// Cached result of the last automatic update check
+    pub static ref UPDATE_CACHE_PATH: PathBuf = {
+        let mut ucp = dirs::config_dir().unwrap();
+        ucp.push("bm2");
+        ucp.push("update-check.json");
+        ucp
+    };
+    /// Directory holding user project templates, one directory per template
+    pub static ref TEMPLATES_PATH: PathBuf = {
+        let mut tp = dirs::config_dir().unwrap();
+        tp.push("bm2");
+        tp.push("templates");
+        tp
+    };
+    /// Directory holding the previous executable for rollbacks
+    pub static ref BACKUP_PATH: PathBuf = {
+        let mut bp = dirs::config_dir().unwrap();
+        bp.push("bm2");
+        bp.push("backup");
+        bp
+    };
 }
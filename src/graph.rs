@@ -0,0 +1,270 @@
+use crate::{
+    error::{ErrorKind, ResultExt},
+    resolve::{self, Relation, RelationKind},
+};
+use anyhow::{anyhow, Result};
+use manifest::Manifest;
+use semver::Version;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::Write,
+};
+
+/// Mod in a dependency graph
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Node {
+    pub id: String,
+    /// Version of the mod, if it's in the set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<Version>,
+    /// Whether the mod is only referenced by others
+    pub missing: bool,
+}
+
+/// Relations between a set of mods, along with their load order
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Relation>,
+    /// Load order, if the mods can be loaded together
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_order: Option<Vec<String>>,
+    /// Reasons the mods can't be loaded together
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<String>,
+}
+
+impl Graph {
+    /// Builds the graph of a set of mods, only keeping the ones reachable from the roots if any are given
+    pub fn new(mut manifests: Vec<Manifest>, roots: &[String]) -> Result<Self> {
+        if !roots.is_empty() {
+            let relations = resolve::relations(&manifests);
+            let reachable = reachable(&relations, roots);
+            if let Some(root) = roots
+                .iter()
+                .find(|r| !manifests.iter().any(|m| &m.id == *r))
+            {
+                return Err(anyhow!("Unknown root mod `{}`", root)).category(ErrorKind::Input);
+            }
+            manifests.retain(|m| reachable.contains(m.id.as_str()));
+        }
+
+        let edges = resolve::relations(&manifests);
+        let mut nodes: BTreeMap<&str, Node> = BTreeMap::new();
+        for m in &manifests {
+            nodes.entry(&m.id).or_insert_with(|| Node {
+                id: m.id.clone(),
+                version: Some(m.version.clone()),
+                missing: false,
+            });
+        }
+        for e in &edges {
+            nodes.entry(&e.to).or_insert_with(|| Node {
+                id: e.to.clone(),
+                version: None,
+                missing: true,
+            });
+        }
+        let nodes = nodes.into_values().collect();
+
+        let (load_order, problems) = match resolve::resolve(&manifests) {
+            Ok(order) => (Some(order.iter().map(|m| m.id.clone()).collect()), vec![]),
+            Err(e) => (None, e.problems.iter().map(ToString::to_string).collect()),
+        };
+        Ok(Self {
+            nodes,
+            edges,
+            load_order,
+            problems,
+        })
+    }
+
+    /// Graphviz DOT representation, missing mods being dashed and each relation kind having its own edge style
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph mods {\n    node [shape=box];\n");
+        for n in &self.nodes {
+            let attributes = match &n.version {
+                Some(v) => format!("label=\"{}\\n{}\"", dot_escape(&n.id), v),
+                None => "style=dashed".to_owned(),
+            };
+            writeln!(dot, "    \"{}\" [{}];", dot_escape(&n.id), attributes).unwrap();
+        }
+        for e in &self.edges {
+            let attributes = match e.kind {
+                RelationKind::DependsOn => "",
+                RelationKind::ConflictsWith => ", color=red, arrowhead=tee",
+                RelationKind::LoadAfter | RelationKind::LoadBefore => ", style=dashed",
+            };
+            writeln!(
+                dot,
+                "    \"{}\" -> \"{}\" [label=\"{}\"{}];",
+                dot_escape(&e.from),
+                dot_escape(&e.to),
+                dot_escape(&edge_label(e)),
+                attributes
+            )
+            .unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Mermaid flowchart representation, missing mods being dashed and each relation kind having its own edge style
+    pub fn to_mermaid(&self) -> String {
+        let mut mermaid = String::from("flowchart LR\n");
+        // Mod IDs can contain dots, which Mermaid doesn't allow in node IDs
+        let ids: BTreeMap<&str, String> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.id.as_str(), format!("n{}", i)))
+            .collect();
+        for n in &self.nodes {
+            let label = match &n.version {
+                Some(v) => format!("{}<br/>{}", n.id, v),
+                None => n.id.clone(),
+            };
+            writeln!(
+                mermaid,
+                "    {}[\"{}\"]",
+                ids[n.id.as_str()],
+                mermaid_escape(&label)
+            )
+            .unwrap();
+            if n.missing {
+                writeln!(
+                    mermaid,
+                    "    style {} stroke-dasharray: 5 5",
+                    ids[n.id.as_str()]
+                )
+                .unwrap();
+            }
+        }
+        for e in &self.edges {
+            let arrow = match e.kind {
+                RelationKind::DependsOn => "-->",
+                RelationKind::ConflictsWith => "--x",
+                RelationKind::LoadAfter | RelationKind::LoadBefore => "-.->",
+            };
+            writeln!(
+                mermaid,
+                "    {} {}|\"{}\"| {}",
+                ids[e.from.as_str()],
+                arrow,
+                mermaid_escape(&edge_label(e)),
+                ids[e.to.as_str()]
+            )
+            .unwrap();
+        }
+        mermaid
+    }
+}
+
+/// IDs of the mods reachable from the roots through any relation, roots included
+fn reachable<'a>(relations: &'a [Relation], roots: &'a [String]) -> BTreeSet<&'a str> {
+    let mut reachable: BTreeSet<&str> = roots.iter().map(String::as_str).collect();
+    let mut queue: VecDeque<&str> = reachable.iter().copied().collect();
+    while let Some(id) = queue.pop_front() {
+        for r in relations.iter().filter(|r| r.from == id) {
+            if reachable.insert(&r.to) {
+                queue.push_back(&r.to);
+            }
+        }
+    }
+    reachable
+}
+
+/// Edge label, the version requirement or the load order hint
+fn edge_label(relation: &Relation) -> String {
+    match &relation.requirement {
+        Some(req) => req.to_string(),
+        None => relation.kind.to_string(),
+    }
+}
+
+/// Escapes a string for a quoted DOT ID
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escapes a string for a quoted Mermaid label
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{graph::Graph, resolve::test_manifest};
+    use manifest::Manifest;
+    use serde_json::json;
+
+    fn manifests() -> Vec<Manifest> {
+        vec![
+            test_manifest(
+                "SongBrowser",
+                "1.0.0",
+                json!({"dependsOn": {"SongCore": "^2.5"}}),
+            ),
+            test_manifest("SongCore", "1.0.0", json!({"dependsOn": {"BSIPA": "^4.0"}})),
+            test_manifest(
+                "Counters",
+                "1.0.0",
+                json!({"conflictsWith": {"Camera": "*"}}),
+            ),
+            test_manifest("Camera", "1.0.0", json!({"loadAfter": ["SongCore"]})),
+        ]
+    }
+
+    #[test]
+    fn roots() {
+        let graph = Graph::new(manifests(), &["SongBrowser".to_owned()]).unwrap();
+        let ids: Vec<&str> = graph.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, vec!["BSIPA", "SongBrowser", "SongCore"]);
+        assert!(graph.nodes[0].missing);
+        assert_eq!(graph.load_order, None);
+        assert_eq!(
+            graph.problems,
+            vec![
+                "`SongCore` 1.0.0 doesn't match ^2.5, required by SongBrowser",
+                "Missing `BSIPA` ^4.0, required by SongBrowser -> SongCore",
+            ]
+        );
+
+        assert!(Graph::new(manifests(), &["Unknown".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn formats() {
+        let graph = Graph::new(manifests(), &["Counters".to_owned()]).unwrap();
+        assert_eq!(
+            graph.to_dot(),
+            r#"digraph mods {
+    node [shape=box];
+    "BSIPA" [style=dashed];
+    "Camera" [label="Camera\n1.0.0"];
+    "Counters" [label="Counters\n1.0.0"];
+    "SongCore" [label="SongCore\n1.0.0"];
+    "Camera" -> "SongCore" [label="loads after", style=dashed];
+    "Counters" -> "Camera" [label="*", color=red, arrowhead=tee];
+    "SongCore" -> "BSIPA" [label="^4.0"];
+}
+"#
+        );
+        assert_eq!(
+            graph.to_mermaid(),
+            r#"flowchart LR
+    n0["BSIPA"]
+    style n0 stroke-dasharray: 5 5
+    n1["Camera<br/>1.0.0"]
+    n2["Counters<br/>1.0.0"]
+    n3["SongCore<br/>1.0.0"]
+    n1 -.->|"loads after"| n3
+    n2 --x|"*"| n1
+    n3 -->|"^4.0"| n0
+"#
+        );
+    }
+}
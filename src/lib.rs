@@ -0,0 +1,32 @@
+//! Library behind the `bm2` CLI for the Beat Saber mod repository BeatMods2
+//!
+//! Everything the CLI does is available here without any terminal side effects:
+//! nothing prompts or writes to the terminal, and long running operations report
+//! their progress through a [`Reporter`](progress::Reporter) instead.
+
+/// Application configuration
+pub mod config;
+/// Manifest fields inferred from existing projects
+pub mod detect;
+/// Error categories and exit codes
+pub mod error;
+/// Global constants and static variables
+pub mod globals;
+/// Dependency graphs of mod sets
+pub mod graph;
+/// Manifest migration from the old to the new format
+pub mod migrate;
+/// Resource packaging
+pub mod package;
+/// Progress reporting
+pub mod progress;
+/// Publishing to BeatMods
+pub mod publish;
+/// Load order resolution across mods
+pub mod resolve;
+/// Project templates for new mods
+pub mod template;
+/// Self updater
+pub mod updater;
+
+pub use manifest;
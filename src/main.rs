@@ -2,41 +2,128 @@
 
 /// CLI subcommands
 mod commands;
-/// Application configuration
-mod config;
-/// Global constants and static variables
-mod globals;
-/// Auto updater
-mod updater;
+/// Machine-readable output
+mod output;
+/// Terminal handles and progress reporting
+mod terminal;
 /// Various utilities and helpers
 mod utils;
 
-use crate::commands::{Command, Run};
-use anyhow::Result;
-use std::env;
-use structopt::StructOpt;
+use crate::{
+    commands::{Command, GlobalOpts, Run},
+    output::CommandOutput,
+    terminal::{TERM_ERR, TERM_OUT},
+};
+use anyhow::{anyhow, Result};
+use bm2::{
+    config::Config,
+    error::{ErrorKind, ResultExt},
+};
+use serde_json::Value;
+use std::{env, process};
+use structopt::{clap, StructOpt};
 
 /// CLI for the Beat Saber mod repository BeatMods2
 #[derive(StructOpt, Debug)]
 #[structopt(author)]
 struct Opt {
-    /// Prints more information
-    #[structopt(short, long)]
-    verbose: bool,
+    #[structopt(flatten)]
+    global: GlobalOpts,
 
     #[structopt(subcommand)]
     cmd: Command,
 }
 
 fn main() -> Result<()> {
-    if env::args().any(|a| &a == "finish_update") {
-        return updater::finish_update();
+    #[cfg(windows)]
+    {
+        use bm2::updater;
+
+        if let Some(exe) = env::var_os(updater::FINISH_UPDATE_ENV) {
+            return updater::finish_update(
+                std::path::Path::new(&exe),
+                &terminal::Spinner::new(false),
+            );
+        }
+        updater::remove_old_exe();
+    }
+
+    let mut opt = match Opt::from_iter_safe(env::args_os()) {
+        Ok(opt) => opt,
+        Err(e) => return arg_error(e),
+    };
+    if !atty::is(atty::Stream::Stdin) {
+        opt.global.non_interactive = true;
     }
-    if config::Config::read()?.auto_update {
-        updater::update(true)?;
+
+    if !opt.global.json() {
+        if let Err(e) = run(opt) {
+            let kind = ErrorKind::of(&e);
+            TERM_ERR.write_line(&format!("Error [{}]: {:?}", kind.id(), e))?;
+            process::exit(kind.code());
+        }
+        return Ok(());
     }
 
-    let opt = Opt::from_args();
-    opt.cmd.run(opt.verbose)?;
+    let output = CommandOutput::new(opt.cmd.name(), run(opt));
+    TERM_OUT.write_line(&serde_json::to_string(&output)?)?;
+    if let Some(e) = output.error {
+        process::exit(e.code);
+    }
     Ok(())
 }
+
+/// Reports a command line parsing error as an input error, exiting like clap for help and version requests
+fn arg_error(e: clap::Error) -> Result<()> {
+    if let clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed = e.kind {
+        e.exit();
+    }
+    let message = console::strip_ansi_codes(&e.message).into_owned();
+    let message = message.trim_start_matches("error: ").trim_end();
+    let kind = ErrorKind::Input;
+
+    // The output format can't be known for sure since parsing failed
+    let args: Vec<String> = env::args().skip(1).collect();
+    let json = args.iter().enumerate().any(|(i, a)| {
+        a == "--output=json"
+            || a == "-ojson"
+            || ((a == "-o" || a == "--output")
+                && args.get(i + 1).map(String::as_str) == Some("json"))
+    });
+    if json {
+        let error = Err(anyhow!("{}", message)).category(kind);
+        let output = CommandOutput::new(String::new(), error);
+        TERM_OUT.write_line(&serde_json::to_string(&output)?)?;
+    } else {
+        TERM_ERR.write_line(&format!("Error [{}]: {}", kind.id(), message))?;
+    }
+    process::exit(kind.code());
+}
+
+/// Checks for updates then runs the command
+fn run(opt: Opt) -> Result<Value> {
+    let reporter = opt.global.reporter();
+    let config = Config::read().category(ErrorKind::Config)?;
+    if config.auto_update {
+        match commands::update(&config, &opt.global, &*reporter) {
+            Ok(status) if status.installed => {
+                // Let the spawned process take over
+                #[cfg(windows)]
+                std::thread::sleep(std::time::Duration::from_millis(250));
+                return Err(anyhow!(
+                    "bm2 was updated to {}, run the command again to use the new version",
+                    status.latest_version.unwrap_or_default()
+                ))
+                .category(ErrorKind::Updated);
+            }
+            Ok(_) => (),
+            // Being offline shouldn't prevent running commands
+            Err(e) if ErrorKind::of(&e) == ErrorKind::Network => {
+                reporter.clear();
+                reporter.info(&format!("Warning: couldn't check for updates: {}", e));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    opt.cmd.run(&opt.global, &*reporter)
+}
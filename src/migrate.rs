@@ -0,0 +1,202 @@
+use crate::progress::Reporter;
+use anyhow::Result;
+use manifest::{Manifest, MigrationNote, OldManifest};
+use similar::TextDiff;
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Name of the manifest files looked up when migrating a directory
+pub const MANIFEST_NAME: &str = "manifest.json";
+/// Directories never looked into when migrating a directory, along with hidden ones
+const IGNORED_DIRS: &[&str] = &["bin", "obj", "node_modules", "target"];
+/// Readme files looked up next to a manifest, in order
+const README_FILES: &[&str] = &["README.md", "readme.md", "README.txt", "README"];
+/// Build output directories looked up next to a manifest, in order
+const BUILD_DIRS: &[&str] = &["bin/Release", "bin"];
+
+/// Migrates an old manifest in a directory to the new format, the result might need edits to be valid
+///
+/// Fields the new format doesn't define are kept or reported, and the readme and published resource
+/// are filled from the files next to the manifest if it doesn't set them.
+pub fn migrate(
+    old_manifest: OldManifest,
+    license: String,
+    dir: &Path,
+) -> (Manifest, Vec<MigrationNote>) {
+    let (mut manifest, mut notes) = old_manifest.migrate(license);
+    if manifest.readme.is_none() {
+        manifest.readme = find_in(dir, README_FILES, Path::is_file);
+        if let Some(readme) = &manifest.readme {
+            notes.push(MigrationNote::Detected {
+                to: "readme".to_owned(),
+                value: readme.display().to_string(),
+            });
+        }
+    }
+    if manifest.publish.resource.is_none() {
+        manifest.publish.resource = find_in(dir, BUILD_DIRS, Path::is_dir);
+        if let Some(resource) = &manifest.publish.resource {
+            notes.push(MigrationNote::Detected {
+                to: "publish.resource".to_owned(),
+                value: resource.display().to_string(),
+            });
+        }
+    }
+    (manifest, notes)
+}
+
+/// First of the given relative paths that exists in a directory, relative to it
+fn find_in(dir: &Path, paths: &[&str], exists: fn(&Path) -> bool) -> Option<PathBuf> {
+    paths
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| exists(&dir.join(p)))
+}
+
+/// Finds the manifests in a directory and its subdirectories, skipping hidden and build output directories
+pub fn find_manifests<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>> {
+    let mut manifests = Vec::new();
+    let walker = WalkDir::new(dir)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name().to_string_lossy();
+            e.depth() == 0
+                || !e.file_type().is_dir()
+                || !(name.starts_with('.') || IGNORED_DIRS.contains(&name.as_ref()))
+        });
+    for entry in walker {
+        let entry = entry?;
+        if entry.file_type().is_file() && entry.file_name() == MANIFEST_NAME {
+            manifests.push(entry.into_path());
+        }
+    }
+    Ok(manifests)
+}
+
+/// Unified diff between a manifest file's content and its migrated version
+pub fn diff(path: &Path, old: &str, new: &str) -> String {
+    let name = path.display().to_string();
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&name, &name)
+        .to_string()
+}
+
+/// Writes the new manifest in place of the old one, returns the backup path if any
+///
+/// The old manifest is backed up as `<file><suffix>`, like `manifest.json.old`, unless no suffix is given.
+pub fn write_migrated<P: AsRef<Path>>(
+    path: P,
+    manifest: &Manifest,
+    backup_suffix: Option<&str>,
+    reporter: &dyn Reporter,
+) -> Result<Option<PathBuf>> {
+    let path = path.as_ref();
+    let backup_path = match backup_suffix {
+        Some(suffix) => {
+            let backup_path = PathBuf::from(format!("{}{}", path.display(), suffix));
+            reporter.detail("Backing up old manifest");
+            fs::rename(path, &backup_path)?;
+            Some(backup_path)
+        }
+        None => None,
+    };
+
+    reporter.detail("Writing new manifest...");
+    let f = File::create(path)?;
+    manifest.to_writer(f)?;
+    Ok(backup_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrate;
+    use manifest::{MigrationNote, OldManifest};
+    use std::{fs, path::Path};
+
+    #[test]
+    fn find_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in &[
+            "manifest.json",
+            "ModA/manifest.json",
+            "ModB/src/manifest.json",
+            "ModB/bin/Release/manifest.json",
+            ".git/manifest.json",
+            "ModC/other.json",
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "{}").unwrap();
+        }
+
+        let found: Vec<_> = migrate::find_manifests(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|p| p.strip_prefix(dir.path()).unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                Path::new("ModA/manifest.json"),
+                Path::new("ModB/src/manifest.json"),
+                Path::new("manifest.json"),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let diff = migrate::diff(
+            Path::new("manifest.json"),
+            "{\n  \"id\": \"MyMod\",\n  \"gameVersion\": \"1.12.2\"\n}",
+            "{\n  \"id\": \"MyMod\",\n  \"gameVersion\": \"1.12.2\",\n  \"license\": \"MIT\"\n}",
+        );
+        assert!(diff.starts_with("--- manifest.json\n+++ manifest.json\n@@ -1,4 +1,5 @@\n"));
+        assert!(
+            diff.contains("\n-  \"gameVersion\": \"1.12.2\"\n+  \"gameVersion\": \"1.12.2\",\n")
+        );
+        assert!(diff.contains("\n+  \"license\": \"MIT\"\n"));
+    }
+
+    #[test]
+    fn detect_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("README.md"), "# Example Mod").unwrap();
+        fs::create_dir_all(dir.path().join("bin").join("Release")).unwrap();
+        let old: OldManifest = r#"{
+          "id": "ExampleMod",
+          "name": "Example Mod",
+          "version": "1.2.3",
+          "gameVersion": "1.12.2",
+          "description": ["This is an example mod."],
+          "author": "DaNike"
+        }"#
+        .parse()
+        .unwrap();
+
+        let (manifest, notes) = migrate::migrate(old, "MIT".to_owned(), dir.path());
+        assert_eq!(manifest.readme.as_deref(), Some(Path::new("README.md")));
+        assert_eq!(
+            manifest.publish.resource.as_deref(),
+            Some(Path::new("bin/Release"))
+        );
+        assert_eq!(
+            notes,
+            vec![
+                MigrationNote::Detected {
+                    to: "readme".to_owned(),
+                    value: "README.md".to_owned(),
+                },
+                MigrationNote::Detected {
+                    to: "publish.resource".to_owned(),
+                    value: "bin/Release".to_owned(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use anyhow::{Error, Result};
+use bm2::error::ErrorKind;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Result of a command in JSON output mode
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandOutput {
+    pub command: String,
+    pub success: bool,
+    /// Whether bm2 was updated instead of running the command
+    pub updated: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorOutput>,
+}
+
+/// Error details in JSON output mode
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorOutput {
+    pub id: String,
+    pub code: i32,
+    pub message: String,
+    pub causes: Vec<String>,
+}
+
+impl From<&Error> for ErrorOutput {
+    fn from(e: &Error) -> Self {
+        let kind = ErrorKind::of(e);
+        Self {
+            id: kind.id(),
+            code: kind.code(),
+            message: e.to_string(),
+            causes: e.chain().skip(1).map(|c| c.to_string()).collect(),
+        }
+    }
+}
+
+impl CommandOutput {
+    /// Wraps the result of a command
+    pub fn new(command: String, result: Result<Value>) -> Self {
+        match result {
+            Ok(v) => Self {
+                command,
+                success: true,
+                updated: false,
+                result: Some(v),
+                error: None,
+            },
+            Err(e) => Self {
+                command,
+                success: false,
+                updated: ErrorKind::of(&e) == ErrorKind::Updated,
+                result: None,
+                error: Some((&e).into()),
+            },
+        }
+    }
+}
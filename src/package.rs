@@ -0,0 +1,99 @@
+use crate::{
+    error::{ErrorKind, ResultExt},
+    progress::Reporter,
+};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    fs::{self, File},
+    io::{Cursor, Read, Seek, Write},
+    path::Path,
+};
+use walkdir::WalkDir;
+use zip::{write::FileOptions, ZipWriter};
+
+/// Zips a folder into the passed writer and returns it
+pub fn zip_dir<P, W>(path: P, writer: W, reporter: &dyn Reporter) -> Result<W>
+where
+    P: AsRef<Path>,
+    W: Write + Seek,
+{
+    let mut zip = ZipWriter::new(writer);
+    let options = FileOptions::default().unix_permissions(0o755);
+
+    let mut buffer = Vec::new();
+    for entry in WalkDir::new(&path).min_depth(1) {
+        let entry = entry?;
+        let entry_path = entry.path();
+        let entry_name = entry_path.strip_prefix(&path)?;
+
+        if entry_path.is_file() {
+            zip.start_file_from_path(entry_name, options)?;
+            let mut f = File::open(entry_path)?;
+            f.read_to_end(&mut buffer)?;
+            zip.write_all(&*buffer)?;
+            buffer.clear();
+        } else {
+            zip.add_directory_from_path(entry_name, options)?;
+        }
+        reporter.detail(&format!("Added file {}", entry_path.display()));
+    }
+
+    Ok(zip.finish()?)
+}
+
+/// Obtains a byte buffer containing the resource to upload, zipping it if it's a directory
+pub fn read_resource<P: AsRef<Path>>(resource_path: P, reporter: &dyn Reporter) -> Result<Vec<u8>> {
+    let resource_path = resource_path.as_ref();
+    reporter.step("Getting resource ready");
+
+    if !resource_path.exists() {
+        return Err(anyhow!("Can't find specified resource")).category(ErrorKind::Packaging);
+    }
+
+    let result = if resource_path.is_dir() {
+        reporter.step("Resource is a directory, zipping");
+
+        let buffer = Cursor::new(Vec::new());
+        zip_dir(resource_path, buffer, reporter)
+            .context("Failed to zip directory")
+            .category(ErrorKind::Packaging)?
+            .into_inner()
+    } else {
+        fs::read(resource_path).category(ErrorKind::Packaging)?
+    };
+    reporter.finish(None);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{package, progress::Reporter};
+    use std::{cell::RefCell, fs, io::Cursor};
+    use zip::ZipArchive;
+
+    #[derive(Default)]
+    struct Recorder(RefCell<Vec<String>>);
+
+    impl Reporter for Recorder {
+        fn step(&self, _message: &str) {}
+        fn finish(&self, _message: Option<&str>) {}
+        fn clear(&self) {}
+        fn info(&self, _message: &str) {}
+        fn detail(&self, message: &str) {
+            self.0.borrow_mut().push(message.to_owned());
+        }
+    }
+
+    #[test]
+    fn read_resource_zips_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("Plugins")).unwrap();
+        fs::write(dir.path().join("Plugins").join("ExampleMod.dll"), b"dll").unwrap();
+
+        let reporter = Recorder::default();
+        let resource = package::read_resource(dir.path(), &reporter).unwrap();
+        let zip = ZipArchive::new(Cursor::new(resource)).unwrap();
+        assert_eq!(zip.len(), 2);
+        assert_eq!(reporter.0.borrow().len(), 2);
+    }
+}
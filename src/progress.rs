@@ -0,0 +1,29 @@
+/// Receives progress information from long running operations
+pub trait Reporter {
+    /// Starts a new step, or updates the description of the current one
+    fn step(&self, message: &str);
+
+    /// Finishes the current step, optionally replacing its description
+    fn finish(&self, message: Option<&str>);
+
+    /// Finishes the current step and hides it
+    fn clear(&self);
+
+    /// Reports a line of information, such as a command being run
+    fn info(&self, message: &str);
+
+    /// Reports a line of detailed information, usually only shown in verbose mode
+    fn detail(&self, message: &str);
+}
+
+/// Reporter that ignores everything
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Silent;
+
+impl Reporter for Silent {
+    fn step(&self, _message: &str) {}
+    fn finish(&self, _message: Option<&str>) {}
+    fn clear(&self) {}
+    fn info(&self, _message: &str) {}
+    fn detail(&self, _message: &str) {}
+}
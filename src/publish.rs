@@ -0,0 +1,286 @@
+use crate::{
+    detect,
+    error::{ErrorKind, ResultExt},
+    progress::Reporter,
+};
+use anyhow::{anyhow, Context, Result};
+use cfg_if::cfg_if;
+use manifest::{
+    template::{self, TemplateError},
+    AnyManifest, Manifest,
+};
+use reqwest::{
+    blocking::{
+        multipart::{Form, Part},
+        ClientBuilder,
+    },
+    StatusCode,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+    process::{Command, ExitStatus, Stdio},
+};
+
+/// BeatMods1 categories (legacy)
+pub static BM1_CATEGORIES: &[&str] = &[
+    "Other",
+    "Core",
+    "Cosmetic",
+    "Practice / Training",
+    "Gameplay",
+    "Stream Tools",
+    "Libraries",
+    "UI Enhancements",
+    "Lighting",
+    "Tweaks / Tools",
+    "Multiplayer",
+    "Text Changes",
+];
+
+/// Details of a published mod
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Published {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    pub game_version: String,
+    pub resource_size: usize,
+    pub resource_sha256: String,
+}
+
+impl Published {
+    /// Describes a manifest and the resource published along with it
+    pub fn new(manifest: &Manifest, resource: &[u8]) -> Self {
+        Self {
+            id: manifest.id.clone(),
+            name: manifest.name.clone(),
+            version: manifest.version.to_string(),
+            game_version: manifest.game_version.clone(),
+            resource_size: resource.len(),
+            resource_sha256: format!("{:x}", Sha256::digest(resource)),
+        }
+    }
+}
+
+/// Reads and parses a manifest file
+pub fn read_manifest<P: AsRef<Path>>(
+    manifest_path: P,
+    reporter: &dyn Reporter,
+) -> Result<Manifest> {
+    let manifest_path = manifest_path.as_ref();
+    reporter.step("Reading manifest");
+
+    if !manifest_path.exists() {
+        return Err(anyhow!(
+            "Can't find manifest file, make sure you are running from the same directory."
+        ))
+        .category(ErrorKind::ManifestParse);
+    }
+
+    let manifest_file = File::open(manifest_path)
+        .context("Failed to read manifest file")
+        .category(ErrorKind::ManifestParse)?;
+    let result =
+        match AnyManifest::from_reader(&manifest_file).category(ErrorKind::ManifestParse)? {
+            AnyManifest::New(m) => m,
+            AnyManifest::Old(_) => {
+                return Err(anyhow!(
+                    "{} is an old format BSIPA manifest, run `bm2 migrate` to convert it",
+                    manifest_path.display()
+                ))
+                .category(ErrorKind::ManifestParse)
+            }
+        };
+    reporter.finish(None);
+    Ok(result)
+}
+
+/// Renders a manifest template with the properties of the C# project next to it and the environment
+pub fn render_manifest<P: AsRef<Path>>(
+    template_path: P,
+    reporter: &dyn Reporter,
+) -> Result<Manifest> {
+    let template_path = template_path.as_ref();
+    reporter.step("Rendering manifest template");
+
+    let text = fs::read_to_string(template_path)
+        .context("Failed to read manifest template")
+        .category(ErrorKind::ManifestParse)?;
+    let dir = match template_path.parent() {
+        Some(p) if p != Path::new("") => p,
+        _ => Path::new("."),
+    };
+    let properties = match detect::find_file(dir, |name| name.ends_with(".csproj"))? {
+        Some(csproj) => {
+            reporter.detail(&format!("Reading properties from {}", csproj.display()));
+            template::msbuild_properties(&fs::read_to_string(csproj)?)
+        }
+        None => HashMap::new(),
+    };
+
+    let manifest = match template::render_manifest(&text, &properties) {
+        Ok(m) => m,
+        Err(e) => {
+            let category = match e {
+                TemplateError::Invalid(_) => ErrorKind::Validation,
+                _ => ErrorKind::ManifestParse,
+            };
+            return Err(e)
+                .with_context(|| format!("Invalid manifest template {}", template_path.display()))
+                .category(category);
+        }
+    };
+    reporter.finish(None);
+    Ok(manifest)
+}
+
+/// Runs a command using the OS specific shell and current working directory
+///
+/// When `stdout_reserved` is set, the command's output is redirected to stderr
+/// so it doesn't end up mixed with machine-readable output.
+pub fn shell_exec(
+    command_str: &str,
+    output: bool,
+    stdout_reserved: bool,
+) -> io::Result<ExitStatus> {
+    cfg_if! {
+        if #[cfg(target_os = "windows")] {
+            let (shell, flag) = ("cmd", "/C");
+        } else {
+            let (shell, flag) = ("sh", "-c");
+        }
+    }
+    let mut cmd = Command::new(shell);
+    cmd.arg(flag);
+    cmd.arg(&command_str);
+    if !output {
+        cmd.stdout(Stdio::null());
+        cmd.stderr(Stdio::null());
+    } else if stdout_reserved {
+        let o = cmd.stderr(Stdio::inherit()).output()?;
+        io::stderr().write_all(&o.stdout)?;
+        return Ok(o.status);
+    }
+    cmd.status()
+}
+
+/// Runs the publish script commands from the manifest
+pub fn run_script(
+    manifest: &Manifest,
+    output: bool,
+    stdout_reserved: bool,
+    reporter: &dyn Reporter,
+) -> Result<()> {
+    reporter.step("Running commands");
+
+    let script = &manifest.publish.script;
+    if script.is_empty() {
+        reporter.finish(Some("No commands to run"));
+        return Ok(());
+    };
+    for command in script {
+        reporter.info(&format!("$ {}", &command));
+
+        let o = shell_exec(&command, output, stdout_reserved)
+            .context("Failed to run command")
+            .category(ErrorKind::Script)?;
+        if !o.success() {
+            return Err(anyhow!("Command did not exit successfully")).category(ErrorKind::Script);
+        }
+    }
+    reporter.finish(None);
+    Ok(())
+}
+
+/// Publishes the mod to BeatMods1 (legacy)
+pub fn publish_bm1(
+    manifest: Manifest,
+    resource: Vec<u8>,
+    category: &str,
+    user: String,
+    password: String,
+    reporter: &dyn Reporter,
+) -> Result<Published> {
+    reporter.step("Publishing to BeatMods1");
+    let published = Published::new(&manifest, &resource);
+
+    let version_string = manifest.version.to_string();
+    let link_string = if let Some(l) = manifest.links.project_home {
+        l.into_string()
+    } else if let Some(l) = manifest.links.project_source {
+        l.into_string()
+    } else {
+        "https://beatmods.com".to_owned()
+    };
+    let description_string = manifest.description.join("\n");
+
+    if !BM1_CATEGORIES.iter().any(|c| c == &category) {
+        return Err(anyhow!("Invalid category")).category(ErrorKind::Input);
+    }
+
+    let mut resource_name = manifest.id.clone();
+    resource_name.push('.');
+    resource_name.push_str(&version_string);
+    resource_name.push_str(".zip");
+    let file = Part::bytes(resource)
+        .file_name(resource_name)
+        .mime_str("application/zip")?;
+
+    let mut form = Form::new()
+        .part("file", file)
+        .text("name", manifest.name)
+        .text("version", manifest.version.to_string())
+        .text("gameVersion", manifest.game_version)
+        .text("link", link_string)
+        .text("description", description_string)
+        .text("category", category.to_owned());
+    if let Some(d) = &manifest.depends_on {
+        let dependencies_string = d
+            .iter()
+            .map(|d| {
+                let mut s = d.0.clone();
+                s.push('@');
+                s.push_str(&d.1.minimum().to_string());
+                s
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        form = form.text("dependencies", dependencies_string);
+    }
+
+    let client = ClientBuilder::new().cookie_store(true).build()?;
+
+    let login_form = [("username", user), ("password", password)];
+    let login_response = client
+        .post("https://beatmods.com/api/v1/signIn")
+        .form(&login_form)
+        .send()
+        .category(ErrorKind::Network)?;
+    let token = login_response
+        .headers()
+        .get("x-access-token")
+        .context("Invalid credentials")
+        .category(ErrorKind::Auth)?
+        .to_str()?;
+
+    let response = client
+        .post("https://beatmods.com/api/v1/mod/create/")
+        .multipart(form)
+        .bearer_auth(token)
+        .send()
+        .category(ErrorKind::Network)?;
+
+    if response.status() != StatusCode::from_u16(200)? {
+        return Err(anyhow!("Publishing failed: {}", response.text()?))
+            .category(ErrorKind::ServerRejection);
+    }
+
+    reporter.finish(None);
+    Ok(published)
+}
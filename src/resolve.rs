@@ -0,0 +1,516 @@
+use crate::{
+    error::{ErrorKind, ResultExt},
+    migrate,
+};
+use anyhow::{Context, Result};
+use manifest::{AnyManifest, Manifest};
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs,
+    path::Path,
+};
+
+/// Kind of relation a manifest declares with another mod
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum RelationKind {
+    DependsOn,
+    ConflictsWith,
+    LoadAfter,
+    LoadBefore,
+}
+
+impl Display for RelationKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            RelationKind::DependsOn => write!(f, "depends on"),
+            RelationKind::ConflictsWith => write!(f, "conflicts with"),
+            RelationKind::LoadAfter => write!(f, "loads after"),
+            RelationKind::LoadBefore => write!(f, "loads before"),
+        }
+    }
+}
+
+/// Relation declared by a manifest, the other mod not necessarily being in the set
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Relation {
+    pub from: String,
+    pub to: String,
+    pub kind: RelationKind,
+    /// Version requirement of dependencies and conflicts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requirement: Option<VersionReq>,
+}
+
+impl Display for Relation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "`{}` {} `{}`", self.from, self.kind, self.to)?;
+        if let Some(req) = &self.requirement {
+            write!(f, " {}", req)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every relation declared by a set of manifests, sorted
+pub fn relations(manifests: &[Manifest]) -> Vec<Relation> {
+    let mut relations = Vec::new();
+    for m in manifests {
+        let requirements = [
+            (RelationKind::DependsOn, &m.depends_on),
+            (RelationKind::ConflictsWith, &m.conflicts_with),
+        ];
+        for (kind, map) in &requirements {
+            for (id, req) in map.iter().flatten() {
+                relations.push(Relation {
+                    from: m.id.clone(),
+                    to: id.clone(),
+                    kind: *kind,
+                    requirement: Some(req.clone()),
+                });
+            }
+        }
+        let hints = [
+            (RelationKind::LoadAfter, &m.load_after),
+            (RelationKind::LoadBefore, &m.load_before),
+        ];
+        for (kind, set) in &hints {
+            for id in set.iter().flatten() {
+                relations.push(Relation {
+                    from: m.id.clone(),
+                    to: id.clone(),
+                    kind: *kind,
+                    requirement: None,
+                });
+            }
+        }
+    }
+    relations.sort_by(|a, b| (&a.from, a.kind, &a.to).cmp(&(&b.from, b.kind, &b.to)));
+    relations
+}
+
+/// Reason a set of mods can't be loaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// Several manifests share an ID
+    Duplicate { id: String },
+    /// A dependency isn't in the set, `chain` leading from a mod nothing depends on to the one requiring it
+    Missing {
+        chain: Vec<String>,
+        id: String,
+        requirement: VersionReq,
+    },
+    /// A dependency is in the set with a version that doesn't match
+    Unsatisfied {
+        chain: Vec<String>,
+        id: String,
+        requirement: VersionReq,
+        version: Version,
+    },
+    /// A mod conflicts with another one in the set
+    Conflict {
+        relation: Relation,
+        version: Version,
+    },
+    /// Load order constraints loop back on themselves
+    Cycle { relations: Vec<Relation> },
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Problem::Duplicate { id } => write!(f, "`{}` is provided more than once", id),
+            Problem::Missing {
+                chain,
+                id,
+                requirement,
+            } => write!(
+                f,
+                "Missing `{}` {}, required by {}",
+                id,
+                requirement,
+                chain.join(" -> ")
+            ),
+            Problem::Unsatisfied {
+                chain,
+                id,
+                requirement,
+                version,
+            } => write!(
+                f,
+                "`{}` {} doesn't match {}, required by {}",
+                id,
+                version,
+                requirement,
+                chain.join(" -> ")
+            ),
+            Problem::Conflict { relation, version } => {
+                write!(f, "{}, found {}", relation, version)
+            }
+            Problem::Cycle { relations } => {
+                let steps: Vec<String> = relations.iter().map(Relation::to_string).collect();
+                write!(f, "Load order cycle: {}", steps.join(", "))
+            }
+        }
+    }
+}
+
+/// Problems preventing a set of mods from being loaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveError {
+    pub problems: Vec<Problem>,
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "Can't resolve the load order")?;
+        for problem in &self.problems {
+            write!(f, "\n- {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ResolveError {}
+
+/// Computes the load order of a set of mods, dependencies and `loadAfter` mods first
+///
+/// Ties are broken by ID so the order is stable. Hints about mods that aren't in the set are ignored,
+/// while missing dependencies, version mismatches, conflicts and cycles are all reported at once.
+pub fn resolve(manifests: &[Manifest]) -> Result<Vec<&Manifest>, ResolveError> {
+    let mut problems = Vec::new();
+    let mut mods = BTreeMap::new();
+    for m in manifests {
+        if mods.insert(m.id.as_str(), m).is_some() {
+            problems.push(Problem::Duplicate { id: m.id.clone() });
+        }
+    }
+    let relations = relations(manifests);
+
+    for r in &relations {
+        let req = r.requirement.as_ref();
+        match (r.kind, mods.get(r.to.as_str()), req) {
+            (RelationKind::DependsOn, None, Some(req)) => problems.push(Problem::Missing {
+                chain: dependents_chain(&r.from, &relations),
+                id: r.to.clone(),
+                requirement: req.clone(),
+            }),
+            (RelationKind::DependsOn, Some(m), Some(req)) if !req.matches(&m.version) => problems
+                .push(Problem::Unsatisfied {
+                    chain: dependents_chain(&r.from, &relations),
+                    id: r.to.clone(),
+                    requirement: req.clone(),
+                    version: m.version.clone(),
+                }),
+            (RelationKind::ConflictsWith, Some(m), Some(req)) if req.matches(&m.version) => {
+                problems.push(Problem::Conflict {
+                    relation: r.clone(),
+                    version: m.version.clone(),
+                })
+            }
+            _ => (),
+        }
+    }
+
+    // Edges from each mod to the mods loading after it, along with the relation causing them
+    let mut after: BTreeMap<&str, Vec<(&str, &Relation)>> = BTreeMap::new();
+    let mut remaining: BTreeMap<&str, usize> = mods.keys().map(|id| (*id, 0)).collect();
+    for r in &relations {
+        if !mods.contains_key(r.to.as_str()) {
+            continue;
+        }
+        let (first, then) = match r.kind {
+            RelationKind::DependsOn | RelationKind::LoadAfter => (r.to.as_str(), r.from.as_str()),
+            RelationKind::LoadBefore => (r.from.as_str(), r.to.as_str()),
+            RelationKind::ConflictsWith => continue,
+        };
+        after.entry(first).or_default().push((then, r));
+        *remaining.get_mut(then).unwrap() += 1;
+    }
+
+    let mut ready: BTreeSet<&str> = remaining
+        .iter()
+        .filter(|(_, n)| **n == 0)
+        .map(|(id, _)| *id)
+        .collect();
+    let mut order = Vec::with_capacity(mods.len());
+    while let Some(id) = ready.iter().next().copied() {
+        ready.remove(id);
+        remaining.remove(id);
+        order.push(mods[id]);
+        for (then, _) in after.get(id).into_iter().flatten() {
+            let n = remaining.get_mut(then).unwrap();
+            *n -= 1;
+            if *n == 0 {
+                ready.insert(*then);
+            }
+        }
+    }
+    if !remaining.is_empty() {
+        problems.push(Problem::Cycle {
+            relations: find_cycle(&remaining, &after),
+        });
+    }
+
+    if problems.is_empty() {
+        Ok(order)
+    } else {
+        Err(ResolveError { problems })
+    }
+}
+
+/// Shortest dependency chain from a mod nothing depends on to the given one
+fn dependents_chain(id: &str, relations: &[Relation]) -> Vec<String> {
+    let mut previous: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(id);
+    let mut root = id;
+    while let Some(current) = queue.pop_front() {
+        let dependents: Vec<&str> = relations
+            .iter()
+            .filter(|r| r.kind == RelationKind::DependsOn && r.to == current)
+            .map(|r| r.from.as_str())
+            .filter(|d| *d != id && !previous.contains_key(d))
+            .collect();
+        if dependents.is_empty() {
+            root = current;
+            break;
+        }
+        for d in dependents {
+            previous.insert(d, current);
+            queue.push_back(d);
+        }
+    }
+
+    let mut chain = vec![root.to_owned()];
+    let mut current = root;
+    while let Some(next) = previous.get(current) {
+        chain.push((*next).to_owned());
+        current = next;
+    }
+    chain
+}
+
+/// Cycle among the mods left out of the load order, as the relations forming it
+fn find_cycle(
+    remaining: &BTreeMap<&str, usize>,
+    after: &BTreeMap<&str, Vec<(&str, &Relation)>>,
+) -> Vec<Relation> {
+    // Every mod left has a predecessor that's also left, so walking back always loops
+    let mut before: BTreeMap<&str, (&str, &Relation)> = BTreeMap::new();
+    for (first, edges) in after {
+        for (then, r) in edges {
+            if remaining.contains_key(first) && remaining.contains_key(then) {
+                before.entry(*then).or_insert((*first, *r));
+            }
+        }
+    }
+
+    let mut path: Vec<(&str, &Relation)> = Vec::new();
+    let mut current = *remaining.keys().next().unwrap();
+    loop {
+        if let Some(start) = path.iter().position(|(id, _)| *id == current) {
+            return path[start..].iter().map(|(_, r)| (*r).clone()).collect();
+        }
+        let (previous, r) = before[current];
+        path.push((current, r));
+        current = previous;
+    }
+}
+
+/// Reads manifests from a file or a directory
+///
+/// A directory is searched for `manifest.json` files, and a file can contain a single manifest
+/// or an array of them, like a registry snapshot. Old format manifests are migrated in memory.
+pub fn read_manifests<P: AsRef<Path>>(path: P) -> Result<Vec<Manifest>> {
+    let path = path.as_ref();
+    let files = if path.is_dir() {
+        migrate::find_manifests(path)
+            .with_context(|| format!("Can't read directory {}", path.display()))
+            .category(ErrorKind::Input)?
+    } else {
+        vec![path.to_owned()]
+    };
+
+    let mut manifests = Vec::new();
+    for file in files {
+        let contents = fs::read_to_string(&file)
+            .with_context(|| format!("Can't open {}", file.display()))
+            .category(ErrorKind::Input)?;
+        let parsed: Vec<Result<AnyManifest>> = match serde_json::from_str(&contents) {
+            Ok(Value::Array(values)) => values
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    v.to_string()
+                        .parse::<AnyManifest>()
+                        .with_context(|| format!("Invalid entry {}", i))
+                })
+                .collect(),
+            _ => vec![contents.parse().map_err(anyhow::Error::from)],
+        };
+        for manifest in parsed {
+            let manifest = manifest
+                .with_context(|| format!("Can't read {}", file.display()))
+                .category(ErrorKind::ManifestParse)?;
+            manifests.push(match manifest {
+                AnyManifest::New(m) => m,
+                AnyManifest::Old(m) => m.migrate(String::new()).0,
+            });
+        }
+    }
+    Ok(manifests)
+}
+
+/// Manifest with the given relations, shared by the tests of the modules working on sets of mods
+#[cfg(test)]
+pub(crate) fn test_manifest(id: &str, version: &str, relations: Value) -> Manifest {
+    let mut value = serde_json::json!({
+        "id": id,
+        "name": id,
+        "version": version,
+        "gameVersion": "1.12.2",
+        "description": [],
+        "author": "raftario",
+        "license": "MIT",
+    });
+    for (k, v) in relations.as_object().unwrap() {
+        value[k] = v.clone();
+    }
+    serde_json::from_value(value).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resolve::{self, test_manifest, Problem, RelationKind};
+    use manifest::Manifest;
+    use serde_json::json;
+    use std::fs;
+
+    fn ids(order: Vec<&Manifest>) -> Vec<&str> {
+        order.into_iter().map(|m| m.id.as_str()).collect()
+    }
+
+    #[test]
+    fn load_order() {
+        let manifests = vec![
+            test_manifest(
+                "SongBrowser",
+                "6.0.0",
+                json!({"dependsOn": {"SongCore": "^2.5"}}),
+            ),
+            test_manifest("SongCore", "2.9.0", json!({"dependsOn": {"BSIPA": "^4.0"}})),
+            test_manifest("BSIPA", "4.1.0", json!({})),
+            test_manifest(
+                "Counters",
+                "1.0.0",
+                json!({"loadAfter": ["SongCore", "Unknown"], "loadBefore": ["SongBrowser"]}),
+            ),
+        ];
+        assert_eq!(
+            ids(resolve::resolve(&manifests).unwrap()),
+            vec!["BSIPA", "SongCore", "Counters", "SongBrowser"]
+        );
+    }
+
+    #[test]
+    fn problems() {
+        let manifests = vec![
+            test_manifest(
+                "SongBrowser",
+                "6.0.0",
+                json!({"dependsOn": {"SongCore": "^2.5"}}),
+            ),
+            test_manifest(
+                "SongCore",
+                "2.9.0",
+                json!({"dependsOn": {"BSIPA": "^4.0", "BSML": "^1.0"}}),
+            ),
+            test_manifest(
+                "BSIPA",
+                "3.0.0",
+                json!({"conflictsWith": {"Counters": "*"}}),
+            ),
+            test_manifest("Counters", "1.0.0", json!({"loadBefore": ["BSIPA"]})),
+            test_manifest("Camera", "1.0.0", json!({"loadAfter": ["Sabers"]})),
+            test_manifest("Sabers", "1.0.0", json!({"dependsOn": {"Camera": "*"}})),
+        ];
+        let problems: Vec<String> = resolve::resolve(&manifests)
+            .unwrap_err()
+            .problems
+            .iter()
+            .map(Problem::to_string)
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                "`BSIPA` conflicts with `Counters` *, found 1.0.0",
+                "`BSIPA` 3.0.0 doesn't match ^4.0, required by SongBrowser -> SongCore",
+                "Missing `BSML` ^1.0, required by SongBrowser -> SongCore",
+                "Load order cycle: `Camera` loads after `Sabers`, `Sabers` depends on `Camera` *",
+            ]
+        );
+    }
+
+    #[test]
+    fn relations() {
+        let manifests = vec![test_manifest(
+            "Counters",
+            "1.0.0",
+            json!({"loadBefore": ["BSIPA"], "dependsOn": {"BSML": "^1.0"}}),
+        )];
+        let kinds: Vec<_> = resolve::relations(&manifests)
+            .into_iter()
+            .map(|r| (r.kind, r.to))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (RelationKind::DependsOn, "BSML".to_owned()),
+                (RelationKind::LoadBefore, "BSIPA".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = dir.path().join("snapshot.json");
+        let new = json!({
+            "id": "SongCore",
+            "name": "SongCore",
+            "version": "2.5.0",
+            "gameVersion": "1.12.2",
+            "description": [],
+            "author": "Kyle1413",
+            "license": "MIT",
+        });
+        let old = json!({
+            "id": "SongBrowser",
+            "name": "Song Browser",
+            "version": "6.0.0",
+            "gameVersion": "1.12.2",
+            "description": [],
+            "author": "Halsafar",
+            "dependsOn": {"SongCore": "^2.5"},
+        });
+        fs::write(&snapshot, json!([new, old]).to_string()).unwrap();
+
+        let manifests = resolve::read_manifests(&snapshot).unwrap();
+        assert_eq!(
+            ids(resolve::resolve(&manifests).unwrap()),
+            ["SongCore", "SongBrowser"]
+        );
+
+        fs::write(&snapshot, json!([new, {"id": "Broken"}]).to_string()).unwrap();
+        let error = format!("{:#}", resolve::read_manifests(&snapshot).unwrap_err());
+        assert!(error.contains("Invalid entry 1"));
+        assert!(error.contains("Invalid old BSIPA manifest"));
+    }
+}
@@ -0,0 +1,276 @@
+use crate::{
+    error::{ErrorKind, ResultExt},
+    globals::TEMPLATES_PATH,
+    progress::Reporter,
+};
+use anyhow::{anyhow, Context, Result};
+use manifest::{template::TemplateError, Manifest};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Templates shipped with bm2, as paths relative to the project directory and their content
+static BUILTIN: &[(&str, &[(&str, &str)])] = &[(
+    "bsipa",
+    &[
+        (
+            "${id}.csproj",
+            include_str!("../templates/bsipa/Project.csproj"),
+        ),
+        ("Plugin.cs", include_str!("../templates/bsipa/Plugin.cs")),
+        (".gitignore", include_str!("../templates/bsipa/gitignore")),
+        ("README.md", include_str!("../templates/bsipa/README.md")),
+    ],
+)];
+
+/// Placeholder values, referenced as `${name}` in templates
+pub type Variables = BTreeMap<&'static str, String>;
+
+/// File of a template, with a path relative to the project directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateFile {
+    pub path: String,
+    pub contents: Vec<u8>,
+}
+
+/// Names of the available templates, user templates first
+pub fn names() -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    if TEMPLATES_PATH.is_dir() {
+        for entry in fs::read_dir(&*TEMPLATES_PATH)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+    }
+    for (name, _) in BUILTIN {
+        if !names.iter().any(|n| n == name) {
+            names.push((*name).to_owned());
+        }
+    }
+    Ok(names)
+}
+
+/// Loads a template by name, user templates taking precedence over built-in ones
+pub fn load(name: &str) -> Result<Vec<TemplateFile>> {
+    let dir = TEMPLATES_PATH.join(name);
+    if dir.is_dir() {
+        return load_dir(&dir);
+    }
+    if let Some((_, files)) = BUILTIN.iter().find(|(n, _)| *n == name) {
+        return Ok(files
+            .iter()
+            .map(|(path, contents)| TemplateFile {
+                path: (*path).to_owned(),
+                contents: contents.as_bytes().to_vec(),
+            })
+            .collect());
+    }
+    Err(anyhow!(
+        "Unknown template `{}`, expected one of {} or a directory in {}",
+        name,
+        names()?.join(", "),
+        TEMPLATES_PATH.display()
+    ))
+    .category(ErrorKind::Input)
+}
+
+/// Loads every file of a template directory
+fn load_dir(dir: &Path) -> Result<Vec<TemplateFile>> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(dir)
+        .min_depth(1)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+    {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path().strip_prefix(dir)?;
+        let path = path
+            .to_str()
+            .with_context(|| format!("Invalid template file name {}", path.display()))?
+            .replace('\\', "/");
+        files.push(TemplateFile {
+            path,
+            contents: fs::read(entry.path())?,
+        });
+    }
+    Ok(files)
+}
+
+/// Placeholder values filled from a manifest
+pub fn variables(manifest: &Manifest, manifest_file: &Path) -> Variables {
+    let mut vars = Variables::new();
+    vars.insert("id", manifest.id.clone());
+    vars.insert("name", manifest.name.clone());
+    vars.insert("version", manifest.version.to_string());
+    vars.insert("gameVersion", manifest.game_version.clone());
+    vars.insert("description", manifest.description.join("\n"));
+    vars.insert("author", manifest.author.clone());
+    vars.insert("license", manifest.license.clone());
+    vars.insert(
+        "manifestFile",
+        manifest_file.to_string_lossy().replace('\\', "/"),
+    );
+    vars
+}
+
+/// Replaces `${name}` placeholders, `$${` being a literal `${`
+pub fn render(text: &str, vars: &Variables) -> Result<String> {
+    manifest::template::render(text, |name| vars.get(name).cloned()).map_err(|e| match e {
+        TemplateError::Unknown(name) => {
+            let names: Vec<&str> = vars.keys().copied().collect();
+            anyhow!(
+                "Unknown placeholder `${{{}}}`, expected one of {}",
+                name,
+                names.join(", ")
+            )
+        }
+        e => e.into(),
+    })
+}
+
+/// Renders a template's paths and text files
+///
+/// Files that aren't valid UTF-8 are copied as is.
+pub fn render_files(files: &[TemplateFile], vars: &Variables) -> Result<Vec<TemplateFile>> {
+    files
+        .iter()
+        .map(|f| {
+            let path = render(&f.path, vars)?;
+            let contents = match std::str::from_utf8(&f.contents) {
+                Ok(text) => render(text, vars)?.into_bytes(),
+                Err(_) => f.contents.clone(),
+            };
+            Ok(TemplateFile { path, contents })
+        })
+        .collect::<Result<_>>()
+        .context("Invalid template")
+        .category(ErrorKind::Input)
+}
+
+/// Writes rendered template files into a directory, only overwriting existing files if asked to
+pub fn write(
+    files: &[TemplateFile],
+    dir: &Path,
+    overwrite: bool,
+    reporter: &dyn Reporter,
+) -> Result<Vec<PathBuf>> {
+    let paths: Vec<PathBuf> = files.iter().map(|f| dir.join(&f.path)).collect();
+    if let Some(existing) = paths.iter().find(|p| !overwrite && p.exists()) {
+        return Err(anyhow!(
+            "{} already exists, use `--force` to overwrite it",
+            existing.display()
+        ))
+        .category(ErrorKind::Input);
+    }
+
+    for (file, path) in files.iter().zip(&paths) {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, &file.contents)?;
+        reporter.detail(&format!("Created {}", path.display()));
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        progress::Silent,
+        template::{self, TemplateFile, Variables},
+    };
+    use std::fs;
+
+    #[test]
+    fn render() {
+        let mut vars = Variables::new();
+        vars.insert("id", "MyMod".to_owned());
+        vars.insert("name", "My Mod".to_owned());
+
+        assert_eq!(
+            template::render("namespace ${id} // ${name}", &vars).unwrap(),
+            "namespace MyMod // My Mod"
+        );
+        assert_eq!(
+            template::render("$(BeatSaberDir) $${id}", &vars).unwrap(),
+            "$(BeatSaberDir) ${id}"
+        );
+        assert!(template::render("${author}", &vars).is_err());
+        assert!(template::render("${id", &vars).is_err());
+    }
+
+    #[test]
+    fn builtin() {
+        let mut vars = Variables::new();
+        for name in &[
+            "id",
+            "name",
+            "version",
+            "gameVersion",
+            "description",
+            "author",
+            "license",
+            "manifestFile",
+        ] {
+            vars.insert(name, format!("<{}>", name));
+        }
+        vars.insert("id", "MyMod".to_owned());
+
+        let files = template::render_files(&template::load("bsipa").unwrap(), &vars).unwrap();
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["MyMod.csproj", "Plugin.cs", ".gitignore", "README.md"]
+        );
+        let csproj = String::from_utf8(files[0].contents.clone()).unwrap();
+        assert!(csproj.contains(r#"<EmbeddedResource Include="<manifestFile>" />"#));
+        assert!(csproj.contains("$(BeatSaberDir)"));
+
+        let binary = TemplateFile {
+            path: "icon.png".to_owned(),
+            contents: vec![0x89, b'$', b'{', 0xff],
+        };
+        let rendered = template::render_files(std::slice::from_ref(&binary), &vars).unwrap();
+        assert_eq!(rendered, vec![binary]);
+    }
+
+    #[test]
+    fn write() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![
+            TemplateFile {
+                path: "Plugin.cs".to_owned(),
+                contents: b"new plugin".to_vec(),
+            },
+            TemplateFile {
+                path: "src/Extra.cs".to_owned(),
+                contents: b"new extra".to_vec(),
+            },
+        ];
+        let plugin = dir.path().join("Plugin.cs");
+        fs::write(&plugin, "old plugin").unwrap();
+
+        assert!(template::write(&files, dir.path(), false, &Silent).is_err());
+        assert_eq!(fs::read_to_string(&plugin).unwrap(), "old plugin");
+        assert!(!dir.path().join("src").exists());
+
+        let written = template::write(&files, dir.path(), true, &Silent).unwrap();
+        assert_eq!(
+            written,
+            vec![plugin.clone(), dir.path().join("src/Extra.cs")]
+        );
+        assert_eq!(fs::read_to_string(&plugin).unwrap(), "new plugin");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("src/Extra.cs")).unwrap(),
+            "new extra"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use bm2::progress::Reporter;
+use console::Term;
+use indicatif::ProgressBar;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::{cell::RefCell, time::Instant};
+
+lazy_static! {
+    pub static ref TERM_OUT: Term = Term::stdout();
+    pub static ref TERM_ERR: Term = Term::stderr();
+}
+
+/// Reporter drawing a ticking spinner for each step on stderr
+pub struct Spinner {
+    bar: RefCell<Option<ProgressBar>>,
+    verbose: bool,
+}
+
+impl Spinner {
+    /// Creates a new spinner reporter, `verbose` also shows the lines of detailed information
+    pub fn new(verbose: bool) -> Self {
+        Self {
+            bar: RefCell::new(None),
+            verbose,
+        }
+    }
+}
+
+impl Reporter for Spinner {
+    fn step(&self, message: &str) {
+        let mut bar = self.bar.borrow_mut();
+        if let Some(p) = &*bar {
+            p.set_message(message);
+            return;
+        }
+
+        let p = ProgressBar::new_spinner();
+        p.set_message(message);
+        p.enable_steady_tick(100);
+        *bar = Some(p);
+    }
+
+    fn finish(&self, message: Option<&str>) {
+        if let Some(p) = self.bar.borrow_mut().take() {
+            match message {
+                Some(m) => p.finish_with_message(m),
+                None => p.finish(),
+            }
+        }
+    }
+
+    fn clear(&self) {
+        if let Some(p) = self.bar.borrow_mut().take() {
+            p.finish_and_clear();
+        }
+    }
+
+    fn info(&self, message: &str) {
+        TERM_ERR.write_line(message).ok();
+    }
+
+    fn detail(&self, message: &str) {
+        if self.verbose {
+            self.info(message);
+        }
+    }
+}
+
+/// Reporter writing a line prefixed with the elapsed time for each event on stderr, suited to CI logs
+pub struct Plain {
+    start: Instant,
+    verbose: bool,
+}
+
+impl Plain {
+    /// Creates a new plain reporter, `verbose` also shows the lines of detailed information
+    pub fn new(verbose: bool) -> Self {
+        Self {
+            start: Instant::now(),
+            verbose,
+        }
+    }
+
+    fn line(&self, message: &str) {
+        let elapsed = self.start.elapsed();
+        TERM_ERR
+            .write_line(&format!(
+                "[{:>4}.{:03}s] {}",
+                elapsed.as_secs(),
+                elapsed.subsec_millis(),
+                message
+            ))
+            .ok();
+    }
+}
+
+impl Reporter for Plain {
+    fn step(&self, message: &str) {
+        self.line(message);
+    }
+
+    fn finish(&self, message: Option<&str>) {
+        if let Some(m) = message {
+            self.line(m);
+        }
+    }
+
+    fn clear(&self) {}
+
+    fn info(&self, message: &str) {
+        self.line(message);
+    }
+
+    fn detail(&self, message: &str) {
+        if self.verbose {
+            self.line(message);
+        }
+    }
+}
+
+/// Progress event written by the JSON events reporter
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Event<'a> {
+    event: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+    elapsed_ms: u128,
+}
+
+/// Reporter writing a JSON object for each event on stderr
+pub struct JsonEvents {
+    start: Instant,
+    verbose: bool,
+}
+
+impl JsonEvents {
+    /// Creates a new JSON reporter, `verbose` also emits the detailed information events
+    pub fn new(verbose: bool) -> Self {
+        Self {
+            start: Instant::now(),
+            verbose,
+        }
+    }
+
+    fn event(&self, event: &str, message: Option<&str>) {
+        let event = Event {
+            event,
+            message,
+            elapsed_ms: self.start.elapsed().as_millis(),
+        };
+        if let Ok(s) = serde_json::to_string(&event) {
+            TERM_ERR.write_line(&s).ok();
+        }
+    }
+}
+
+impl Reporter for JsonEvents {
+    fn step(&self, message: &str) {
+        self.event("step", Some(message));
+    }
+
+    fn finish(&self, message: Option<&str>) {
+        self.event("finish", message);
+    }
+
+    fn clear(&self) {
+        self.event("finish", None);
+    }
+
+    fn info(&self, message: &str) {
+        self.event("info", Some(message));
+    }
+
+    fn detail(&self, message: &str) {
+        if self.verbose {
+            self.event("detail", Some(message));
+        }
+    }
+}
@@ -1,34 +1,349 @@
-use crate::globals::{TERM_ERR, USER_AGENT};
-use anyhow::{Context, Result};
+use crate::{
+    error::{ErrorKind, ResultExt},
+    globals::{BACKUP_PATH, UPDATE_CACHE_PATH, USER_AGENT},
+    progress::Reporter,
+};
+use anyhow::{anyhow, Context, Result};
 use cfg_if::cfg_if;
-use dialoguer::Confirmation;
-use indicatif::ProgressBar;
+use ed25519_dalek::{PublicKey, Signature};
+use flate2::read::GzDecoder;
 use lazy_static::lazy_static;
-use reqwest::blocking::ClientBuilder;
-use semver::{SemVerError, Version};
-use serde::Deserialize;
+use reqwest::{
+    blocking::{Client, ClientBuilder, RequestBuilder},
+    header::{HeaderValue, AUTHORIZATION},
+    Url,
+};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use semver::{SemVerError, Version, VersionReq};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
-    convert::{TryFrom, TryInto},
-    env, fs,
+    convert::TryFrom,
+    env::{
+        self,
+        consts::{ARCH, OS},
+    },
+    ffi::OsStr,
+    fmt::{self, Display},
+    fs,
     io::{Cursor, Read},
-    process::{self, Command},
-    thread,
-    time::Duration,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
+use tar::Archive;
 use zip::ZipArchive;
 
 const RELEASES_URL: &str = "https://api.github.com/repos/raftario/bm2/releases";
 cfg_if! {
     if #[cfg(target_os = "windows")] {
-        const RELEASE_ASSET_NAME: &str = "Windows.zip";
-    } else if #[cfg(target_os = "macos")] {
-        const RELEASE_ASSET_NAME: &str = "macOS.zip";
+        const BINARY_NAME: &str = "bm2.exe";
     } else {
-        const RELEASE_ASSET_NAME: &str = "Linux.zip";
+        const BINARY_NAME: &str = "bm2";
     }
 }
+/// Release asset listing the SHA-256 of the other assets, in `sha256sum` format
+const CHECKSUMS_ASSET_NAME: &str = "SHA256SUMS";
+/// Release asset containing the hex encoded ed25519 signature of the release version and checksums asset
+const SIGNATURE_ASSET_NAME: &str = "SHA256SUMS.sig";
+/// Timeout for fetching the release list, so checks don't hold up commands
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Environment variable marking a process spawned to finish an update, set to the executable to replace
+pub const FINISH_UPDATE_ENV: &str = "BM2_FINISH_UPDATE";
+/// Environment variable set to the version of the replaced executable when finishing an update, unset for rollbacks
+const FINISH_UPDATE_BACKUP_ENV: &str = "BM2_FINISH_UPDATE_BACKUP";
+/// Hex encoded ed25519 public key releases are signed with, embedded at build time
+const UPDATE_PUBLIC_KEY: Option<&str> = option_env!("BM2_UPDATE_PUBLIC_KEY");
 lazy_static! {
-    static ref VERSION: Version = Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
+    /// Version of the running executable
+    pub static ref VERSION: Version = Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
+}
+
+/// Releases followed by the updater
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum UpdateChannel {
+    /// Stable releases only
+    Stable,
+    /// Stable releases and prereleases
+    Prerelease,
+    /// Stable releases matching a version requirement
+    Pinned(VersionReq),
+}
+
+#[allow(clippy::derivable_impls)] // `#[default]` on variants needs Rust 1.62
+impl Default for UpdateChannel {
+    fn default() -> Self {
+        Self::Stable
+    }
+}
+
+impl UpdateChannel {
+    /// Whether the channel follows the given release version
+    pub fn accepts(&self, version: &Version) -> bool {
+        match self {
+            Self::Stable => !version.is_prerelease(),
+            Self::Prerelease => true,
+            Self::Pinned(req) => req.matches(version),
+        }
+    }
+}
+
+impl FromStr for UpdateChannel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stable" => Ok(Self::Stable),
+            "prerelease" => Ok(Self::Prerelease),
+            _ => Ok(Self::Pinned(VersionReq::parse(s).map_err(|_| {
+                anyhow!(
+                    "Invalid update channel `{}`, expected `stable`, `prerelease` or a version requirement",
+                    s
+                )
+            })?)),
+        }
+    }
+}
+
+impl Display for UpdateChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stable => f.write_str("stable"),
+            Self::Prerelease => f.write_str("prerelease"),
+            Self::Pinned(req) => req.fmt(f),
+        }
+    }
+}
+
+impl TryFrom<String> for UpdateChannel {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<UpdateChannel> for String {
+    fn from(value: UpdateChannel) -> Self {
+        value.to_string()
+    }
+}
+
+impl JsonSchema for UpdateChannel {
+    fn schema_name() -> String {
+        "UpdateChannel".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema = String::json_schema(gen).into_object();
+        let metadata = schema.metadata();
+        metadata.description = Some(
+            "Either `stable`, `prerelease` or a version requirement such as `^0.3`".to_owned(),
+        );
+        metadata.examples = vec!["stable".into(), "prerelease".into(), "^0.3".into()];
+        schema.into()
+    }
+}
+
+/// Where releases are looked up
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum UpdateSource {
+    /// GitHub compatible releases endpoint
+    #[serde(rename_all = "camelCase")]
+    Github {
+        /// Releases endpoint URL
+        url: String,
+        /// Value of the `Authorization` header sent with requests to the origin of the URL
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        authorization: Option<String>,
+    },
+    /// JSON index listing releases and their assets, asset URLs may be relative to the index
+    #[serde(rename_all = "camelCase")]
+    Index {
+        /// Index URL
+        url: String,
+        /// Value of the `Authorization` header sent with requests to the origin of the URL
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        authorization: Option<String>,
+    },
+    /// Local directory containing a directory of assets per release, named after its version
+    Directory {
+        /// Directory path
+        path: PathBuf,
+    },
+}
+
+impl Default for UpdateSource {
+    fn default() -> Self {
+        Self::Github {
+            url: RELEASES_URL.to_owned(),
+            authorization: None,
+        }
+    }
+}
+
+impl UpdateSource {
+    /// Value of the `Authorization` header for a URL, only set for URLs with the same origin as the source
+    fn authorization(&self, url: &Url) -> Result<Option<HeaderValue>> {
+        let (source_url, authorization) = match self {
+            Self::Github {
+                url,
+                authorization: Some(a),
+            }
+            | Self::Index {
+                url,
+                authorization: Some(a),
+            } => (url, a),
+            _ => return Ok(None),
+        };
+        if Url::parse(source_url).ok().map(|u| u.origin()) != Some(url.origin()) {
+            return Ok(None);
+        }
+
+        let mut value = HeaderValue::from_str(authorization)
+            .context("Invalid update source authorization header")
+            .category(ErrorKind::Config)?;
+        value.set_sensitive(true);
+        Ok(Some(value))
+    }
+
+    /// HTTP request to a URL listed by this source
+    fn get(&self, client: &Client, url: &str) -> Result<RequestBuilder> {
+        let url = Url::parse(url)
+            .with_context(|| format!("Invalid update URL `{}`", url))
+            .category(ErrorKind::Update)?;
+        if url.scheme() != "https" && url.scheme() != "http" {
+            return Err(anyhow!("Unsupported update URL `{}`", url)).category(ErrorKind::Update);
+        }
+
+        let authorization = self.authorization(&url)?;
+        let mut request = client.get(url);
+        if let Some(a) = authorization {
+            request = request.header(AUTHORIZATION, a);
+        }
+        Ok(request)
+    }
+
+    /// Key identifying this source in the update check cache, without any credentials
+    fn cache_key(&self) -> String {
+        match self {
+            Self::Github { url, .. } => format!("github:{}", url),
+            Self::Index { url, .. } => format!("index:{}", url),
+            Self::Directory { path } => format!("directory:{}", path.display()),
+        }
+    }
+
+    /// Fetches the releases listed by this source
+    fn releases(&self, client: &Client) -> Result<Vec<VersionedRelease>> {
+        match self {
+            Self::Github { url, .. } => {
+                let releases: Vec<Release> = fetch_json(self, client, url)?;
+                Ok(releases
+                    .into_iter()
+                    .filter_map(|r| VersionedRelease::try_from(r).ok())
+                    .collect())
+            }
+            Self::Index { url, .. } => {
+                let base = Url::parse(url)
+                    .context("Invalid update index URL")
+                    .category(ErrorKind::Config)?;
+                let index: Index = fetch_json(self, client, url)?;
+                Ok(index
+                    .releases
+                    .into_iter()
+                    .map(|r| VersionedRelease {
+                        version: r.version,
+                        assets: r
+                            .assets
+                            .into_iter()
+                            .filter_map(|a| {
+                                Some(ReleaseAsset {
+                                    browser_download_url: base.join(&a.url).ok()?.into(),
+                                    name: a.name,
+                                })
+                            })
+                            .collect(),
+                    })
+                    .collect())
+            }
+            Self::Directory { path } => directory_releases(path)
+                .with_context(|| format!("Failed to read releases from {}", path.display()))
+                .category(ErrorKind::Update),
+        }
+    }
+}
+
+/// HTTP client used for all update requests
+fn client() -> Result<Client> {
+    Ok(ClientBuilder::new().user_agent(USER_AGENT).build()?)
+}
+
+/// Fetches and parses a JSON document from a source with the check timeout
+fn fetch_json<T: DeserializeOwned>(source: &UpdateSource, client: &Client, url: &str) -> Result<T> {
+    source
+        .get(client, url)?
+        .timeout(CHECK_TIMEOUT)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .category(ErrorKind::Network)?
+        .json()
+        .category(ErrorKind::Network)
+}
+
+/// Lists the releases in a local directory source, ignoring entries not named after a version
+fn directory_releases(path: &Path) -> Result<Vec<VersionedRelease>> {
+    let mut releases = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let version = match entry
+            .file_name()
+            .to_str()
+            .and_then(|n| Version::parse(n.trim_start_matches('v')).ok())
+        {
+            Some(v) if entry.file_type()?.is_dir() => v,
+            _ => continue,
+        };
+
+        let mut assets = Vec::new();
+        for asset in fs::read_dir(entry.path())? {
+            let asset = asset?;
+            let url = match Url::from_file_path(fs::canonicalize(asset.path())?) {
+                Ok(u) => u,
+                Err(_) => continue,
+            };
+            if let Some(name) = asset.file_name().to_str() {
+                assets.push(ReleaseAsset {
+                    name: name.to_owned(),
+                    browser_download_url: url.into(),
+                });
+            }
+        }
+        releases.push(VersionedRelease { version, assets });
+    }
+    Ok(releases)
+}
+
+/// Release index served by an index update source
+#[derive(Deserialize, Debug)]
+struct Index {
+    releases: Vec<IndexRelease>,
+}
+
+/// Release listed in an index
+#[derive(Deserialize, Debug)]
+struct IndexRelease {
+    version: Version,
+    assets: Vec<IndexAsset>,
+}
+
+/// Release asset listed in an index
+#[derive(Deserialize, Debug)]
+struct IndexAsset {
+    name: String,
+    url: String,
 }
 
 /// Required GitHub release info
@@ -49,7 +364,7 @@ impl TryFrom<Release> for VersionedRelease {
 
     fn try_from(value: Release) -> Result<Self, Self::Error> {
         Ok(Self {
-            version: Version::parse(&value.tag_name[1..])?,
+            version: Version::parse(value.tag_name.trim_start_matches('v'))?,
             assets: value.assets,
         })
     }
@@ -62,121 +377,1103 @@ struct ReleaseAsset {
     browser_download_url: String,
 }
 
-/// Check for updates and installs them
-pub fn update(clear: bool) -> Result<()> {
-    let p = ProgressBar::new_spinner();
-    p.set_message("Checking for updates");
-    p.enable_steady_tick(100);
+/// Names of the release assets for a platform, by order of preference
+///
+/// Assets are named `bm2-<arch>-<os>` with a `.tar.gz` or `.zip` extension,
+/// where `<arch>` and `<os>` are Rust's names for them (`x86_64-linux`, `aarch64-macos`, ...).
+/// Releases predating the convention only have a zip per OS for x86_64.
+fn asset_names(os: &str, arch: &str) -> Vec<String> {
+    let mut names = vec![
+        format!("bm2-{}-{}.tar.gz", arch, os),
+        format!("bm2-{}-{}.zip", arch, os),
+    ];
+    let legacy = match os {
+        "windows" => Some("Windows.zip"),
+        "macos" => Some("macOS.zip"),
+        "linux" => Some("Linux.zip"),
+        _ => None,
+    };
+    if let (Some(l), "x86_64") = (legacy, arch) {
+        names.push(l.to_owned());
+    }
+    names
+}
 
-    let client = ClientBuilder::new().user_agent(USER_AGENT).build()?;
+impl VersionedRelease {
+    /// Download URL of the named asset
+    fn asset_url(&self, name: &str) -> Option<String> {
+        self.assets
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| a.browser_download_url.clone())
+    }
 
-    let releases: Vec<Release> = client.get(RELEASES_URL).send()?.json()?;
-    let mut new_releases: Vec<VersionedRelease> = releases
-        .into_iter()
-        .filter_map(|r| r.try_into().ok())
-        .filter(|vr: &VersionedRelease| {
-            vr.version > *VERSION && vr.assets.iter().any(|a| a.name == RELEASE_ASSET_NAME)
+    /// Update to this release, if it has a signed asset for the given platform
+    fn update(&self, os: &str, arch: &str) -> Option<AvailableUpdate> {
+        let (asset_name, url) = asset_names(os, arch)
+            .into_iter()
+            .find_map(|n| self.asset_url(&n).map(|u| (n, u)))?;
+        Some(AvailableUpdate {
+            version: self.version.clone(),
+            asset_name,
+            url,
+            checksums_url: self.asset_url(CHECKSUMS_ASSET_NAME)?,
+            signature_url: self.asset_url(SIGNATURE_ASSET_NAME)?,
         })
-        .collect();
-    if new_releases.is_empty() {
-        if clear {
-            p.finish_and_clear();
-        } else {
-            p.finish()
-        }
-        return Ok(());
     }
-    new_releases.sort_by(|a, b| b.version.cmp(&a.version));
+}
 
-    let new_version = &new_releases[0].version;
-    let url = &new_releases[0]
-        .assets
-        .iter()
-        .find(|a| a.name == RELEASE_ASSET_NAME)
-        .unwrap()
-        .browser_download_url;
+/// Signed release available for the running platform
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AvailableUpdate {
+    pub version: Version,
+    asset_name: String,
+    url: String,
+    checksums_url: String,
+    signature_url: String,
+}
+
+/// Checks the source for a newer release on the given channel, `clear` hides the progress once done
+pub fn check(
+    source: &UpdateSource,
+    channel: &UpdateChannel,
+    clear: bool,
+    reporter: &dyn Reporter,
+) -> Result<Option<AvailableUpdate>> {
+    reporter.step("Checking for updates");
+    let releases = releases(source)?;
 
     if clear {
-        p.finish_and_clear();
+        reporter.clear();
     } else {
-        p.finish()
-    }
-    let install = Confirmation::new()
-        .with_text(&format!(
-            "A new version is available, do you want to update? (current: {}, new: {})",
-            &*VERSION, new_version
-        ))
-        .interact_on(&*TERM_ERR)?;
-    if clear {
-        TERM_ERR.clear_last_lines(1)?;
-    }
-    if !install {
-        return Ok(());
+        reporter.finish(None);
     }
+    Ok(newest(releases, channel, &VERSION))
+}
+
+/// Checks for a newer release like `check`, reusing the cached result if it's more recent than `interval`
+pub fn check_cached(
+    source: &UpdateSource,
+    channel: &UpdateChannel,
+    interval: Duration,
+    clear: bool,
+    reporter: &dyn Reporter,
+) -> Result<Option<AvailableUpdate>> {
+    cached(
+        &UPDATE_CACHE_PATH,
+        source,
+        channel,
+        interval,
+        SystemTime::now(),
+        || check(source, channel, clear, reporter),
+    )
+}
+
+/// Looks for a specific release in the source, which may be older than the running executable
+pub fn find(
+    source: &UpdateSource,
+    version: &Version,
+    reporter: &dyn Reporter,
+) -> Result<AvailableUpdate> {
+    reporter.step(&format!("Looking for version {}", version));
+    let release = releases(source)?
+        .into_iter()
+        .find(|r| &r.version == version)
+        .with_context(|| format!("No signed release of version {} for this platform", version))
+        .category(ErrorKind::Update)?;
+    reporter.finish(None);
+    Ok(release)
+}
+
+/// Fetches the signed releases available for the running platform
+fn releases(source: &UpdateSource) -> Result<Vec<AvailableUpdate>> {
+    Ok(source
+        .releases(&client()?)?
+        .into_iter()
+        .filter_map(|vr| vr.update(OS, ARCH))
+        .collect())
+}
+
+/// Newest release followed by the channel that is newer than the current version
+fn newest(
+    releases: Vec<AvailableUpdate>,
+    channel: &UpdateChannel,
+    current: &Version,
+) -> Option<AvailableUpdate> {
+    releases
+        .into_iter()
+        .filter(|r| &r.version > current && channel.accepts(&r.version))
+        .max_by(|a, b| a.version.cmp(&b.version))
+}
 
-    let p = ProgressBar::new_spinner();
-    p.set_message("Downloading new update");
-    p.enable_steady_tick(100);
-    let mut dl = client.get(url).send()?;
+/// Result of the last automatic update check
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CheckCache {
+    /// Seconds since the Unix epoch
+    last_check: u64,
+    /// Source the check was made against, without its credentials
+    source: String,
+    channel: String,
+    update: Option<AvailableUpdate>,
+}
 
-    p.set_message("Installing new update");
-    let mut asset = Cursor::new(Vec::new());
-    dl.copy_to(&mut asset)?;
-    let mut zip = ZipArchive::new(asset)?;
-    let mut zipfile = None;
-    for i in 0..(zip.len()) {
-        if zip.by_index(i)?.is_file() {
-            zipfile = Some(zip.by_index(i)?);
-            break;
+/// Returns the cached check result if it's fresh and for the same source and channel, or runs the check and caches it
+///
+/// Failed checks are cached as finding no update, so they're only retried once the interval has passed.
+/// Unreadable caches are treated as stale and failing to write one isn't an error.
+fn cached<F>(
+    cache_path: &Path,
+    source: &UpdateSource,
+    channel: &UpdateChannel,
+    interval: Duration,
+    now: SystemTime,
+    check: F,
+) -> Result<Option<AvailableUpdate>>
+where
+    F: FnOnce() -> Result<Option<AvailableUpdate>>,
+{
+    let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let cache = fs::read(cache_path)
+        .ok()
+        .and_then(|c| serde_json::from_slice::<CheckCache>(&c).ok());
+    if let Some(c) = cache {
+        let fresh = now.saturating_sub(c.last_check) < interval.as_secs();
+        if fresh && c.source == source.cache_key() && c.channel == channel.to_string() {
+            return Ok(c.update.filter(|u| u.version > *VERSION));
         }
     }
-    let mut zipfile = zipfile.context("Missing file in GitHub download")?;
-    let mut file = Vec::with_capacity(zipfile.size() as usize);
-    zipfile.read_to_end(&mut file)?;
 
-    let current_exe = env::current_exe()?;
-    cfg_if! {
-        if #[cfg(windows)] {
-            let old_exe = format!("{}.old.exe", current_exe.display());
-            let new_exe = format!("{}.new.exe", current_exe.display());
+    let result = check();
+    let cache = CheckCache {
+        last_check: now,
+        source: source.cache_key(),
+        channel: channel.to_string(),
+        update: result.as_ref().ok().cloned().flatten(),
+    };
+    if let Ok(c) = serde_json::to_vec_pretty(&cache) {
+        if let Some(dir) = cache_path.parent() {
+            fs::create_dir_all(dir).ok();
+        }
+        fs::write(cache_path, c).ok();
+    }
+    result
+}
+
+/// Downloads the body of a URL listed by a source, failing on error statuses
+///
+/// Directory sources list `file` URLs, which are read instead.
+fn download(source: &UpdateSource, client: &Client, url: &str) -> Result<Vec<u8>> {
+    if let UpdateSource::Directory { .. } = source {
+        let path = Url::parse(url)
+            .ok()
+            .and_then(|u| u.to_file_path().ok())
+            .with_context(|| format!("Invalid release asset path `{}`", url))
+            .category(ErrorKind::Update)?;
+        return fs::read(path).category(ErrorKind::Update);
+    }
+
+    let mut response = source
+        .get(client, url)?
+        .send()
+        .and_then(|r| r.error_for_status())
+        .category(ErrorKind::Network)?;
+    let mut body = Vec::new();
+    response.copy_to(&mut body).category(ErrorKind::Network)?;
+    Ok(body)
+}
+
+/// Public key embedded in this build
+fn embedded_public_key() -> Result<PublicKey> {
+    let key = UPDATE_PUBLIC_KEY
+        .context("This build can't verify updates, download the new version manually")
+        .category(ErrorKind::Update)?;
+    let key = hex::decode(key.trim())
+        .ok()
+        .and_then(|k| PublicKey::from_bytes(&k).ok())
+        .context("Invalid embedded update public key")
+        .category(ErrorKind::Update)?;
+    Ok(key)
+}
+
+/// Data covered by a release signature, the version so old releases can't be served as newer ones, then the checksums
+fn signed_payload(version: &Version, checksums: &[u8]) -> Vec<u8> {
+    let mut payload = format!("bm2 v{}\n", version).into_bytes();
+    payload.extend_from_slice(checksums);
+    payload
+}
+
+/// Finds the checksum of the named file in a `sha256sum` formatted list
+fn find_checksum<'a>(checksums: &'a str, name: &str) -> Option<&'a str> {
+    checksums.lines().find_map(|l| {
+        let mut parts = l.split_whitespace();
+        let checksum = parts.next()?;
+        let file_name = parts.next()?.trim_start_matches('*');
+        if file_name == name {
+            Some(checksum)
         } else {
-            let old_exe = format!("{}.old", current_exe.display());
-            let new_exe = format!("{}.new", current_exe.display());
+            None
         }
+    })
+}
+
+/// Downloads the release asset of an update, verifying the checksums signature and the asset checksum
+fn download_verified(
+    source: &UpdateSource,
+    update: &AvailableUpdate,
+    public_key: &PublicKey,
+) -> Result<Vec<u8>> {
+    let client = client()?;
+
+    let checksums = download(source, &client, &update.checksums_url)?;
+    let signature = download(source, &client, &update.signature_url)?;
+    let signature = String::from_utf8(signature)
+        .ok()
+        .and_then(|s| hex::decode(s.trim()).ok())
+        .and_then(|s| Signature::try_from(&s[..]).ok())
+        .context("Invalid release signature")
+        .category(ErrorKind::Update)?;
+    public_key
+        .verify_strict(&signed_payload(&update.version, &checksums), &signature)
+        .context("Release signature doesn't match, refusing to install")
+        .category(ErrorKind::Update)?;
+
+    let checksums = String::from_utf8(checksums)
+        .context("Invalid release checksums")
+        .category(ErrorKind::Update)?;
+    let expected = find_checksum(&checksums, &update.asset_name)
+        .context("Release checksums don't list the downloaded asset")
+        .category(ErrorKind::Update)?;
+
+    let asset = download(source, &client, &update.url)?;
+    let actual = format!("{:x}", Sha256::digest(&asset));
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(anyhow!(
+            "Downloaded asset checksum doesn't match, refusing to install"
+        ))
+        .category(ErrorKind::Update);
     }
-    fs::copy(&current_exe, &old_exe)?;
-    fs::write(&new_exe, file)?;
+    Ok(asset)
+}
 
-    if clear {
-        p.finish_and_clear();
+/// Whether an archive entry path points to the executable, wherever it is in the archive
+fn is_executable(path: &Path) -> bool {
+    path.file_name() == Some(OsStr::new(BINARY_NAME))
+}
+
+/// Extracts the executable from a `.tar.gz` or `.zip` release asset
+fn extract_executable(asset_name: &str, asset: Vec<u8>) -> Result<Vec<u8>> {
+    let mut file = Vec::new();
+    if asset_name.ends_with(".tar.gz") {
+        let mut archive = Archive::new(GzDecoder::new(Cursor::new(asset)));
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_file() && is_executable(&entry.path()?) {
+                entry.read_to_end(&mut file)?;
+                return Ok(file);
+            }
+        }
     } else {
-        p.finish()
+        let mut zip = ZipArchive::new(Cursor::new(asset))?;
+        for i in 0..(zip.len()) {
+            let mut zipfile = zip.by_index(i)?;
+            if zipfile.is_file() && is_executable(Path::new(zipfile.name())) {
+                zipfile.read_to_end(&mut file)?;
+                return Ok(file);
+            }
+        }
     }
-    Command::new(old_exe).arg("finish_update").spawn()?;
-    thread::sleep(Duration::from_millis(250));
-    process::exit(0);
+    Err(anyhow!(
+        "Missing {} in release asset {}",
+        BINARY_NAME,
+        asset_name
+    ))
 }
-// This is required cause Windows doesn't let you move an executable if it's running
-pub fn finish_update() -> Result<()> {
-    let p = ProgressBar::new_spinner();
-    p.set_message("Finalizing");
-    p.enable_steady_tick(100);
 
-    thread::sleep(Duration::from_millis(500));
+/// Downloads, verifies and installs an update in place of the running executable
+///
+/// The running process should exit promptly once this returns, as the update is finished by a child process on Windows.
+pub fn install(
+    source: &UpdateSource,
+    update: &AvailableUpdate,
+    clear: bool,
+    reporter: &dyn Reporter,
+) -> Result<()> {
+    let public_key = embedded_public_key()?;
+
+    reporter.step("Downloading new update");
+    let asset = download_verified(source, update, &public_key)?;
+
+    reporter.step("Installing new update");
+    let file = extract_executable(&update.asset_name, asset).category(ErrorKind::Update)?;
+    stage(&file, Some(&VERSION), clear, reporter)
+}
+
+/// Reinstalls the backed up executable in place of the running one, then forgets the backup
+///
+/// The running process should exit promptly once this returns, as the rollback is finished by a child process on Windows.
+pub fn rollback(reporter: &dyn Reporter) -> Result<Version> {
+    reporter.step("Rolling back");
+    let version = read_backup(&BACKUP_PATH)?
+        .context("No previous version to roll back to")
+        .category(ErrorKind::Update)?;
+    let file = fs::read(backup_exe_path(&BACKUP_PATH)).category(ErrorKind::Update)?;
+    stage(&file, None, false, reporter)?;
+    Ok(version)
+}
+
+/// Replaces the running executable with a new one, backing up the current one as `backup` if given
+///
+/// On Unix the new executable is renamed into place and checked right away.
+/// Windows doesn't let a running executable be replaced, so a copy of it is spawned
+/// with the `FINISH_UPDATE_ENV` marker to finish the job once this process exits.
+fn stage(
+    file: &[u8],
+    backup: Option<&Version>,
+    clear: bool,
+    reporter: &dyn Reporter,
+) -> Result<()> {
     let current_exe = env::current_exe()?;
-    let current_exe = current_exe.to_str().context("Invalid current exe path")?;
+
     cfg_if! {
         if #[cfg(windows)] {
-            let old_exe = &current_exe[..(current_exe.len() - 8)];
-            let new_exe = format!("{}.new.exe", old_exe);
+            let old_exe = sibling(&current_exe, ".old.exe");
+            fs::copy(&current_exe, &old_exe)?;
+            fs::write(sibling(&current_exe, ".new"), file)?;
+
+            if clear {
+                reporter.clear();
+            } else {
+                reporter.finish(None);
+            }
+            let mut command = Command::new(old_exe);
+            command.env(FINISH_UPDATE_ENV, &current_exe);
+            if let Some(version) = backup {
+                command.env(FINISH_UPDATE_BACKUP_ENV, version.to_string());
+            }
+            command.spawn()?;
         } else {
-            let old_exe = &current_exe[..(current_exe.len() - 4)];
-            let new_exe = format!("{}.new", old_exe);
+            let previous = fs::read(&current_exe)?;
+            replace_exe(&current_exe, file)?;
+            reporter.step("Checking new version");
+            check_or_restore(&current_exe, &previous)?;
+            record_backup(&previous, backup)?;
+
+            if clear {
+                reporter.clear();
+            } else {
+                reporter.finish(None);
+            }
         }
     }
-    fs::remove_file(old_exe)?;
-    fs::rename(new_exe, old_exe)?;
+    Ok(())
+}
+
+/// Path next to `path` with a suffix appended to its file name
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Atomically replaces an executable, keeping its permissions
+#[cfg(unix)]
+fn replace_exe(exe: &Path, contents: &[u8]) -> Result<()> {
+    let new_exe = sibling(exe, ".new");
+    let result = fs::write(&new_exe, contents)
+        .and_then(|_| fs::set_permissions(&new_exe, fs::metadata(exe)?.permissions()))
+        .and_then(|_| fs::rename(&new_exe, exe));
+    if result.is_err() {
+        fs::remove_file(&new_exe).ok();
+    }
+    Ok(result?)
+}
+
+/// Replaces an executable that isn't running
+#[cfg(not(unix))]
+fn replace_exe(exe: &Path, contents: &[u8]) -> Result<()> {
+    Ok(fs::write(exe, contents)?)
+}
+
+/// Metadata of the backed up executable
+#[derive(Serialize, Deserialize, Debug)]
+struct BackupInfo {
+    version: String,
+}
 
-    p.finish_and_clear();
+/// Path of the backed up executable in the backup directory
+fn backup_exe_path(dir: &Path) -> PathBuf {
+    if cfg!(windows) {
+        dir.join("bm2.exe")
+    } else {
+        dir.join("bm2")
+    }
+}
+
+/// Writes an executable and records its version in the backup directory
+fn save_backup(dir: &Path, exe: &[u8], version: &Version) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(backup_exe_path(dir), exe)?;
+    let info = BackupInfo {
+        version: version.to_string(),
+    };
+    fs::write(dir.join("backup.json"), serde_json::to_vec_pretty(&info)?)?;
+    Ok(())
+}
+
+/// Removes the backed up executable and its version, if any
+fn clear_backup(dir: &Path) -> Result<()> {
+    for path in &[dir.join("backup.json"), backup_exe_path(dir)] {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
     Ok(())
 }
+
+/// Records the replaced executable once the new one is known to work
+///
+/// Updates back up the previous executable, while rollbacks consume the backup so they can't be repeated.
+fn record_backup(previous: &[u8], backup: Option<&Version>) -> Result<()> {
+    match backup {
+        Some(version) => save_backup(&BACKUP_PATH, previous, version),
+        None => clear_backup(&BACKUP_PATH),
+    }
+    .context("Failed to update the backup of the previous version")
+    .category(ErrorKind::Update)
+}
+
+/// Version of the backed up executable, if there is one
+fn read_backup(dir: &Path) -> Result<Option<Version>> {
+    let info_path = dir.join("backup.json");
+    if !info_path.exists() || !backup_exe_path(dir).exists() {
+        return Ok(None);
+    }
+    let info: BackupInfo = serde_json::from_slice(&fs::read(info_path)?)?;
+    Ok(Some(Version::parse(&info.version)?))
+}
+
+/// Whether the executable starts and reports its version
+fn health_check(exe: &Path) -> bool {
+    Command::new(exe)
+        .arg("--version")
+        .env_remove(FINISH_UPDATE_ENV)
+        .env_remove(FINISH_UPDATE_BACKUP_ENV)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Checks a newly installed executable, restoring the previous one if it fails to start
+fn check_or_restore(exe: &Path, previous: &[u8]) -> Result<()> {
+    if health_check(exe) {
+        return Ok(());
+    }
+
+    replace_exe(exe, previous)
+        .context("New version failed to start and restoring the previous one failed")
+        .category(ErrorKind::Update)?;
+    Err(anyhow!(
+        "New version failed to start, rolled back to the previous version"
+    ))
+    .category(ErrorKind::Update)
+}
+
+/// Moves a staged update into place from a copy of the previous executable, for Windows
+///
+/// `exe` is the path of the executable to replace, passed through `FINISH_UPDATE_ENV`,
+/// and is only touched if an update was staged next to it.
+#[cfg(windows)]
+pub fn finish_update(exe: &Path, reporter: &dyn Reporter) -> Result<()> {
+    let new_exe = sibling(exe, ".new");
+    if !new_exe.is_file() {
+        return Err(anyhow!("No staged update to finish for {}", exe.display()))
+            .category(ErrorKind::Update);
+    }
+    reporter.step("Finalizing");
+
+    // Wait for the process that spawned this one to exit
+    std::thread::sleep(Duration::from_millis(500));
+    // This process runs from a copy of the previous executable
+    let previous = fs::read(env::current_exe()?)?;
+    fs::rename(&new_exe, exe)?;
+
+    reporter.step("Checking new version");
+    let backup = env::var(FINISH_UPDATE_BACKUP_ENV)
+        .ok()
+        .and_then(|v| Version::parse(&v).ok());
+    let result =
+        check_or_restore(exe, &previous).and_then(|_| record_backup(&previous, backup.as_ref()));
+    reporter.clear();
+    result
+}
+
+/// Removes the copy of the previous executable left behind by `finish_update`, for Windows
+///
+/// It can't remove itself, so this runs on the next start and failures are ignored.
+#[cfg(windows)]
+pub fn remove_old_exe() {
+    if let Ok(exe) = env::current_exe() {
+        fs::remove_file(sibling(&exe, ".old.exe")).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::ErrorKind,
+        progress::Silent,
+        updater::{
+            self, AvailableUpdate, Release, UpdateChannel, UpdateSource, VersionedRelease,
+            BINARY_NAME, CHECKSUMS_ASSET_NAME, SIGNATURE_ASSET_NAME,
+        },
+    };
+    use anyhow::{anyhow, Result};
+    use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+    use flate2::{write::GzEncoder, Compression};
+    use mockito::Mock;
+    use reqwest::Url;
+    use semver::Version;
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+    use std::{
+        convert::TryFrom,
+        env::consts::{ARCH, OS},
+        fs,
+        io::{Cursor, Write},
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+    use zip::{write::FileOptions, ZipWriter};
+
+    /// Trimmed down response of the GitHub releases endpoint
+    const RECORDED_RELEASES: &str = r#"[
+        {
+            "url": "https://api.github.com/repos/raftario/bm2/releases/3",
+            "tag_name": "v0.5.0",
+            "name": "0.5.0",
+            "prerelease": false,
+            "assets": [
+                { "name": "bm2-x86_64-linux.tar.gz", "size": 4021, "browser_download_url": "https://github.com/raftario/bm2/releases/download/v0.5.0/bm2-x86_64-linux.tar.gz" },
+                { "name": "bm2-aarch64-linux.tar.gz", "size": 3986, "browser_download_url": "https://github.com/raftario/bm2/releases/download/v0.5.0/bm2-aarch64-linux.tar.gz" },
+                { "name": "bm2-x86_64-macos.tar.gz", "size": 3874, "browser_download_url": "https://github.com/raftario/bm2/releases/download/v0.5.0/bm2-x86_64-macos.tar.gz" },
+                { "name": "bm2-aarch64-macos.zip", "size": 3850, "browser_download_url": "https://github.com/raftario/bm2/releases/download/v0.5.0/bm2-aarch64-macos.zip" },
+                { "name": "bm2-x86_64-windows.zip", "size": 4410, "browser_download_url": "https://github.com/raftario/bm2/releases/download/v0.5.0/bm2-x86_64-windows.zip" },
+                { "name": "SHA256SUMS", "size": 470, "browser_download_url": "https://github.com/raftario/bm2/releases/download/v0.5.0/SHA256SUMS" },
+                { "name": "SHA256SUMS.sig", "size": 128, "browser_download_url": "https://github.com/raftario/bm2/releases/download/v0.5.0/SHA256SUMS.sig" }
+            ]
+        },
+        {
+            "url": "https://api.github.com/repos/raftario/bm2/releases/2",
+            "tag_name": "v0.4.0",
+            "name": "0.4.0",
+            "prerelease": false,
+            "assets": [
+                { "name": "Linux.zip", "size": 3920, "browser_download_url": "https://github.com/raftario/bm2/releases/download/v0.4.0/Linux.zip" },
+                { "name": "macOS.zip", "size": 3801, "browser_download_url": "https://github.com/raftario/bm2/releases/download/v0.4.0/macOS.zip" },
+                { "name": "Windows.zip", "size": 4302, "browser_download_url": "https://github.com/raftario/bm2/releases/download/v0.4.0/Windows.zip" },
+                { "name": "SHA256SUMS", "size": 210, "browser_download_url": "https://github.com/raftario/bm2/releases/download/v0.4.0/SHA256SUMS" },
+                { "name": "SHA256SUMS.sig", "size": 128, "browser_download_url": "https://github.com/raftario/bm2/releases/download/v0.4.0/SHA256SUMS.sig" }
+            ]
+        },
+        {
+            "url": "https://api.github.com/repos/raftario/bm2/releases/1",
+            "tag_name": "v0.3.0",
+            "name": "0.3.0",
+            "prerelease": false,
+            "assets": [
+                { "name": "Linux.zip", "size": 3911, "browser_download_url": "https://github.com/raftario/bm2/releases/download/v0.3.0/Linux.zip" }
+            ]
+        }
+    ]"#;
+
+    fn asset_name() -> String {
+        format!("bm2-{}-{}.zip", ARCH, OS)
+    }
+
+    fn keypair(seed: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    fn asset(contents: &[u8]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("README.md", FileOptions::default()).unwrap();
+        zip.write_all(b"readme").unwrap();
+        zip.start_file(format!("bm2/{}", BINARY_NAME), FileOptions::default())
+            .unwrap();
+        zip.write_all(contents).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    /// Hex encoded signature of a release
+    fn sign(signer: &Keypair, version: &str, checksums: &str) -> String {
+        let payload =
+            updater::signed_payload(&Version::parse(version).unwrap(), checksums.as_bytes());
+        hex::encode(signer.sign(&payload).to_bytes())
+    }
+
+    /// Serves a signed release under `prefix`, requiring the authorization header if any
+    fn serve_release(
+        prefix: &str,
+        authorization: Option<&str>,
+        asset: &[u8],
+        checksums: &str,
+        signer: &Keypair,
+    ) -> (UpdateSource, Vec<Mock>) {
+        let url = |name: &str| format!("{}{}/{}", mockito::server_url(), prefix, name);
+        let asset_name = asset_name();
+        let releases = json!([{
+            "tag_name": "v99.0.0",
+            "assets": [
+                { "name": asset_name, "browser_download_url": url(&asset_name) },
+                { "name": CHECKSUMS_ASSET_NAME, "browser_download_url": url(CHECKSUMS_ASSET_NAME) },
+                { "name": SIGNATURE_ASSET_NAME, "browser_download_url": url(SIGNATURE_ASSET_NAME) },
+            ],
+        }]);
+        let signature = sign(signer, "99.0.0", checksums);
+
+        let mock = |name: &str, body: &[u8]| {
+            let mut mock = mockito::mock("GET", format!("{}/{}", prefix, name).as_str());
+            if let Some(a) = authorization {
+                mock = mock.match_header("authorization", a);
+            }
+            mock.with_body(body).create()
+        };
+        let mocks = vec![
+            mock("releases", releases.to_string().as_bytes()),
+            mock(&asset_name, asset),
+            mock(CHECKSUMS_ASSET_NAME, checksums.as_bytes()),
+            mock(SIGNATURE_ASSET_NAME, signature.as_bytes()),
+        ];
+        let source = UpdateSource::Github {
+            url: url("releases"),
+            authorization: authorization.map(ToOwned::to_owned),
+        };
+        (source, mocks)
+    }
+
+    /// Writes a signed release to a directory source
+    fn write_release(dir: &Path, version: &str, asset: &[u8], checksums: &str, signer: &Keypair) {
+        let release = dir.join(version);
+        fs::create_dir_all(&release).unwrap();
+        fs::write(release.join(asset_name()), asset).unwrap();
+        fs::write(release.join(CHECKSUMS_ASSET_NAME), checksums).unwrap();
+        let signature = sign(signer, version.trim_start_matches('v'), checksums);
+        fs::write(release.join(SIGNATURE_ASSET_NAME), signature).unwrap();
+    }
+
+    fn checksums(asset: &[u8]) -> String {
+        format!(
+            "{:x}  {}\n{:x}  Other.zip\n",
+            Sha256::digest(asset),
+            asset_name(),
+            Sha256::digest(b"other")
+        )
+    }
+
+    fn check(source: &UpdateSource) -> AvailableUpdate {
+        updater::check(source, &UpdateChannel::Stable, true, &Silent)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn verified_update() {
+        let signer = keypair(1);
+        let asset = asset(b"new bm2");
+        let (source, _mocks) = serve_release(
+            "/verified",
+            Some("token secret"),
+            &asset,
+            &checksums(&asset),
+            &signer,
+        );
+
+        let update = check(&source);
+        assert_eq!(update.version.to_string(), "99.0.0");
+        let downloaded = updater::download_verified(&source, &update, &signer.public).unwrap();
+        assert_eq!(
+            updater::extract_executable(&asset_name(), downloaded).unwrap(),
+            b"new bm2"
+        );
+    }
+
+    #[test]
+    fn tampered_asset() {
+        let signer = keypair(2);
+        let asset = asset(b"new bm2");
+        let checksums = checksums(b"something else");
+        let (source, _mocks) = serve_release("/tampered", None, &asset, &checksums, &signer);
+
+        let update = check(&source);
+        let err = updater::download_verified(&source, &update, &signer.public).unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Update);
+    }
+
+    #[test]
+    fn wrong_signer() {
+        let asset = asset(b"new bm2");
+        let (source, _mocks) =
+            serve_release("/unsigned", None, &asset, &checksums(&asset), &keypair(3));
+
+        let update = check(&source);
+        let err = updater::download_verified(&source, &update, &keypair(4).public).unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Update);
+    }
+
+    #[test]
+    fn replayed_signature() {
+        let signer = keypair(6);
+        let dir = tempfile::tempdir().unwrap();
+        let old = asset(b"old bm2");
+        write_release(dir.path(), "99.0.0", &old, &checksums(&old), &signer);
+        // Signature of an older release with the same assets
+        let release = dir.path().join("99.0.0");
+        fs::write(
+            release.join(SIGNATURE_ASSET_NAME),
+            sign(&signer, "0.1.0", &checksums(&old)),
+        )
+        .unwrap();
+
+        let source = UpdateSource::Directory {
+            path: dir.path().to_owned(),
+        };
+        let update = check(&source);
+        let err = updater::download_verified(&source, &update, &signer.public).unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Update);
+    }
+
+    #[test]
+    fn embedded_public_key() {
+        // Release builds must embed a valid key, local builds refuse to install updates
+        assert_eq!(
+            updater::embedded_public_key().is_ok(),
+            updater::UPDATE_PUBLIC_KEY.is_some()
+        );
+    }
+
+    #[test]
+    fn directory_source() {
+        let signer = keypair(5);
+        let dir = tempfile::tempdir().unwrap();
+        let old = asset(b"old bm2");
+        let new = asset(b"new bm2");
+        write_release(dir.path(), "0.1.0", &old, &checksums(&old), &signer);
+        write_release(dir.path(), "v99.0.0", &new, &checksums(&new), &signer);
+        fs::create_dir(dir.path().join("drafts")).unwrap();
+
+        let source = UpdateSource::Directory {
+            path: dir.path().to_owned(),
+        };
+        let update = check(&source);
+        assert_eq!(update.version.to_string(), "99.0.0");
+        let downloaded = updater::download_verified(&source, &update, &signer.public).unwrap();
+        assert_eq!(
+            updater::extract_executable(&asset_name(), downloaded).unwrap(),
+            b"new bm2"
+        );
+
+        let old_version = Version::parse("0.1.0").unwrap();
+        let downgrade = updater::find(&source, &old_version, &Silent).unwrap();
+        let downloaded = updater::download_verified(&source, &downgrade, &signer.public).unwrap();
+        assert_eq!(
+            updater::extract_executable(&asset_name(), downloaded).unwrap(),
+            b"old bm2"
+        );
+    }
+
+    /// Serves a signed release through an index listing the given asset URLs
+    fn serve_index(prefix: &str, urls: [String; 3], signer: &Keypair) -> (UpdateSource, Vec<Mock>) {
+        let asset = asset(b"new bm2");
+        let checksums = checksums(&asset);
+        let names = [
+            asset_name(),
+            CHECKSUMS_ASSET_NAME.to_owned(),
+            SIGNATURE_ASSET_NAME.to_owned(),
+        ];
+        let index = json!({
+            "releases": [{
+                "version": "99.0.0",
+                "assets": names
+                    .iter()
+                    .zip(&urls)
+                    .map(|(name, url)| json!({ "name": name, "url": url }))
+                    .collect::<Vec<_>>(),
+            }],
+        });
+
+        let mock = |path: &str, body: &[u8]| {
+            mockito::mock("GET", format!("{}/{}", prefix, path).as_str())
+                .match_header("authorization", "token secret")
+                .with_body(body)
+                .create()
+        };
+        let mocks = vec![
+            mock("index.json", index.to_string().as_bytes()),
+            mock(&format!("v99.0.0/{}", names[0]), &asset),
+            mock(&format!("v99.0.0/{}", names[1]), checksums.as_bytes()),
+            mock(
+                &format!("v99.0.0/{}", names[2]),
+                sign(signer, "99.0.0", &checksums).as_bytes(),
+            ),
+        ];
+        let source = UpdateSource::Index {
+            url: format!("{}{}/index.json", mockito::server_url(), prefix),
+            authorization: Some("token secret".to_owned()),
+        };
+        (source, mocks)
+    }
+
+    #[test]
+    fn index_source() {
+        let signer = keypair(7);
+        let urls = [
+            format!("v99.0.0/{}", asset_name()),
+            format!("v99.0.0/{}", CHECKSUMS_ASSET_NAME),
+            format!("/index/v99.0.0/{}", SIGNATURE_ASSET_NAME),
+        ];
+        let (source, _mocks) = serve_index("/index", urls, &signer);
+
+        let update = check(&source);
+        assert_eq!(update.version.to_string(), "99.0.0");
+        let downloaded = updater::download_verified(&source, &update, &signer.public).unwrap();
+        assert_eq!(
+            updater::extract_executable(&asset_name(), downloaded).unwrap(),
+            b"new bm2"
+        );
+    }
+
+    #[test]
+    fn index_source_local_files() {
+        let signer = keypair(8);
+        let dir = tempfile::tempdir().unwrap();
+        let asset = asset(b"new bm2");
+        write_release(dir.path(), "99.0.0", &asset, &checksums(&asset), &signer);
+        let release = fs::canonicalize(dir.path().join("99.0.0")).unwrap();
+        let url = |name: &str| Url::from_file_path(release.join(name)).unwrap().to_string();
+        let urls = [
+            url(&asset_name()),
+            url(CHECKSUMS_ASSET_NAME),
+            url(SIGNATURE_ASSET_NAME),
+        ];
+        let (source, _mocks) = serve_index("/local", urls, &signer);
+
+        let update = check(&source);
+        let err = updater::download_verified(&source, &update, &signer.public).unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Update);
+    }
+
+    #[test]
+    fn authorization_origin() {
+        let source = UpdateSource::Index {
+            url: "https://bm2.example/releases/index.json".to_owned(),
+            authorization: Some("token secret".to_owned()),
+        };
+        let authorization = |url: &str| source.authorization(&Url::parse(url).unwrap()).unwrap();
+        assert!(authorization("https://bm2.example/v1.0.0/bm2.tar.gz").is_some());
+        assert!(authorization("https://cdn.example/v1.0.0/bm2.tar.gz").is_none());
+        assert!(authorization("http://bm2.example/v1.0.0/bm2.tar.gz").is_none());
+        assert!(authorization("file:///v1.0.0/bm2.tar.gz").is_none());
+    }
+
+    fn release(version: &str) -> AvailableUpdate {
+        AvailableUpdate {
+            version: Version::parse(version).unwrap(),
+            asset_name: String::new(),
+            url: String::new(),
+            checksums_url: String::new(),
+            signature_url: String::new(),
+        }
+    }
+
+    fn newest(channel: &str, current: &str) -> Option<String> {
+        let releases = ["0.3.0", "0.4.0", "0.5.0-beta.1", "1.0.0", "1.1.0-rc.1"]
+            .iter()
+            .map(|v| release(v))
+            .collect();
+        updater::newest(
+            releases,
+            &channel.parse().unwrap(),
+            &Version::parse(current).unwrap(),
+        )
+        .map(|r| r.version.to_string())
+    }
+
+    #[test]
+    fn channels() {
+        assert_eq!(newest("stable", "0.3.0").as_deref(), Some("1.0.0"));
+        assert_eq!(newest("prerelease", "0.3.0").as_deref(), Some("1.1.0-rc.1"));
+        assert_eq!(newest("^0.3", "0.3.0"), None);
+        assert_eq!(newest("<1.0.0", "0.3.0").as_deref(), Some("0.4.0"));
+        assert_eq!(newest("stable", "1.0.0"), None);
+        assert!("not a channel".parse::<UpdateChannel>().is_err());
+    }
+
+    #[test]
+    fn backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_dir = dir.path().join("backup");
+
+        assert!(updater::read_backup(&backup_dir).unwrap().is_none());
+        updater::save_backup(&backup_dir, b"old bm2", &Version::parse("0.2.0").unwrap()).unwrap();
+        assert_eq!(
+            updater::read_backup(&backup_dir).unwrap(),
+            Some(Version::parse("0.2.0").unwrap())
+        );
+        assert_eq!(
+            fs::read(updater::backup_exe_path(&backup_dir)).unwrap(),
+            b"old bm2"
+        );
+
+        updater::clear_backup(&backup_dir).unwrap();
+        assert!(updater::read_backup(&backup_dir).unwrap().is_none());
+        assert!(!updater::backup_exe_path(&backup_dir).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn health_check() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = |name: &str, code: u8| {
+            let path = dir.path().join(name);
+            fs::write(&path, format!("#!/bin/sh\nexit {}\n", code)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            path
+        };
+
+        assert!(updater::health_check(&script("healthy", 0)));
+        assert!(!updater::health_check(&script("broken", 1)));
+        assert!(!updater::health_check(&dir.path().join("missing")));
+    }
+
+    #[test]
+    fn cached_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("update-check.json");
+        let source = UpdateSource::default();
+        let stable = UpdateChannel::Stable;
+        let interval = Duration::from_secs(60 * 60);
+        let start = SystemTime::now();
+        let check = || Ok(Some(release("99.0.0")));
+        let offline = || -> Result<Option<AvailableUpdate>> { Err(anyhow!("offline")) };
+
+        let first = updater::cached(&cache, &source, &stable, interval, start, check).unwrap();
+        assert_eq!(first.unwrap().version.to_string(), "99.0.0");
+
+        // Fresh cache for the same channel doesn't check again
+        let later = start + Duration::from_secs(60);
+        let cached = updater::cached(&cache, &source, &stable, interval, later, offline).unwrap();
+        assert_eq!(cached.unwrap().version.to_string(), "99.0.0");
+
+        // Changing channel or waiting out the interval does
+        let prerelease = UpdateChannel::Prerelease;
+        assert!(updater::cached(&cache, &source, &prerelease, interval, later, offline).is_err());
+        let expired = start + interval;
+        assert!(updater::cached(&cache, &source, &stable, interval, expired, offline).is_err());
+
+        // A failed check isn't retried until the interval has passed again
+        let retry = expired + Duration::from_secs(60);
+        let none = updater::cached(&cache, &source, &stable, interval, retry, check).unwrap();
+        assert!(none.is_none());
+        let retry = expired + interval;
+        let online = updater::cached(&cache, &source, &stable, interval, retry, check).unwrap();
+        assert_eq!(online.unwrap().version.to_string(), "99.0.0");
+    }
+
+    fn select(os: &str, arch: &str) -> Vec<(String, String)> {
+        let releases: Vec<Release> = serde_json::from_str(RECORDED_RELEASES).unwrap();
+        releases
+            .into_iter()
+            .filter_map(|r| VersionedRelease::try_from(r).unwrap().update(os, arch))
+            .map(|u| (u.version.to_string(), u.asset_name))
+            .collect()
+    }
+
+    #[test]
+    fn asset_selection() {
+        let pair = |v: &str, n: &str| (v.to_owned(), n.to_owned());
+        assert_eq!(
+            select("linux", "x86_64"),
+            vec![
+                pair("0.5.0", "bm2-x86_64-linux.tar.gz"),
+                pair("0.4.0", "Linux.zip")
+            ]
+        );
+        assert_eq!(
+            select("linux", "aarch64"),
+            vec![pair("0.5.0", "bm2-aarch64-linux.tar.gz")]
+        );
+        assert_eq!(
+            select("macos", "aarch64"),
+            vec![pair("0.5.0", "bm2-aarch64-macos.zip")]
+        );
+        assert_eq!(
+            select("windows", "x86_64"),
+            vec![
+                pair("0.5.0", "bm2-x86_64-windows.zip"),
+                pair("0.4.0", "Windows.zip")
+            ]
+        );
+        assert!(select("windows", "aarch64").is_empty());
+    }
+
+    #[test]
+    fn tar_gz_extraction() {
+        let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let mut append = |path: &str, contents: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            tar.append_data(&mut header, path, contents).unwrap();
+        };
+        append("LICENSE", b"license");
+        append(&format!("bm2/{}", BINARY_NAME), b"new bm2");
+        let archive = tar.into_inner().unwrap().finish().unwrap();
+
+        let name = format!("bm2-{}-{}.tar.gz", ARCH, OS);
+        assert_eq!(
+            updater::extract_executable(&name, archive).unwrap(),
+            b"new bm2"
+        );
+        assert!(updater::extract_executable(&asset_name(), asset(b"")).is_ok());
+        assert!(updater::extract_executable("Other.zip", Vec::new()).is_err());
+    }
+
+    #[test]
+    fn sibling_paths() {
+        let exe = Path::new("/opt/bêta/bm2 ✓");
+        assert_eq!(
+            updater::sibling(exe, ".new"),
+            Path::new("/opt/bêta/bm2 ✓.new")
+        );
+        assert_eq!(
+            updater::sibling(Path::new("bm2.exe"), ".old.exe"),
+            Path::new("bm2.exe.old.exe")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replace_exe() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("bm2");
+        fs::write(&exe, b"old bm2").unwrap();
+        fs::set_permissions(&exe, fs::Permissions::from_mode(0o750)).unwrap();
+
+        updater::replace_exe(&exe, b"new bm2").unwrap();
+        assert_eq!(fs::read(&exe).unwrap(), b"new bm2");
+        let mode = fs::metadata(&exe).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}